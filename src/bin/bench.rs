@@ -0,0 +1,58 @@
+//! Measures the performance of the wave equation kernels
+//!
+//! cargo run --release --bin bench [--features parallel]
+//!
+
+use std::time::Instant;
+
+use wave_simulation::wave_equation::Kernel;
+use wave_simulation::wave_equation::Stencil;
+use wave_simulation::wave_equation::WaveEquation;
+
+const STEPS: usize = 200;
+
+// Returns the number of nodes updated per second
+fn cells_per_second(width: usize, height: usize, kernel: Kernel, stencil: Stencil, threads: usize) -> f64 {
+    let mut wave_equation = WaveEquation::new(width, height);
+    wave_equation.set_kernel(kernel);
+    wave_equation.set_stencil(stencil);
+    wave_equation.set_threads(threads);
+
+    // warm up with a short stroke of the mouse
+    for i in 0..10 {
+        wave_equation.add_impulse(height as f32 / 2.0 + 0.3, width as f32 / 4.0 + 0.7 * i as f32 + 0.4);
+        wave_equation.step(None);
+    }
+
+    let start = Instant::now();
+    for _i in 0..STEPS {
+        wave_equation.step(None);
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    if let Some(divergence) = wave_equation.divergence() {
+        panic!("{}", divergence);
+    }
+
+    (width * height * STEPS) as f64 / seconds
+}
+
+fn main() {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut thread_counts = vec![1];
+    if cfg!(feature = "parallel") && threads > 1 {
+        thread_counts.push(threads);
+    }
+
+    println!("{:>10} {:>12} {:>8} {:>14} {:>14} {:>8}", "grid", "stencil", "threads", "per node", "interior", "speedup");
+    for scale in [4, 6, 12] {
+        let (width, height) = (70 * scale, 80 * scale);
+        for stencil in [Stencil::FivePoint, Stencil::NinePoint, Stencil::FourthOrder] {
+            for &threads in &thread_counts {
+                let before = cells_per_second(width, height, Kernel::PerNode, stencil, threads);
+                let after = cells_per_second(width, height, Kernel::Interior, stencil, threads);
+                println!("{:>10} {:>12} {:>8} {:>12.1}M/s {:>12.1}M/s {:>7.2}x",
+                    format!("{width}x{height}"), format!("{stencil:?}"), threads, before / 1e6, after / 1e6, after / before);
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Runs the wave equation without a window or a GPU, e.g. in batch jobs
+//!
+//! cargo run --release --bin headless -- --steps 1000 --probe 140,160 --output output
+//!
+//! Writes the final displacement and fields, a snapshot of the final state, the probe traces and statistics
+//! to the output directory, and optionally png frames, a gif or a y4m video of the displacement and a series of VTK files. Run with --help to list all options.
+//!
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::bail;
+
+use wave_simulation::wave_equation::Boundaries;
+use wave_simulation::wave_equation::BoundaryCondition;
+use wave_simulation::wave_equation::ColorRange;
+use wave_simulation::wave_equation::Colormap;
+use wave_simulation::wave_equation::CropRegion;
+use wave_simulation::wave_equation::Field;
+use wave_simulation::wave_equation::FrameOptions;
+use wave_simulation::wave_equation::GifWriter;
+use wave_simulation::wave_equation::InputRecording;
+use wave_simulation::wave_equation::Integrator;
+use wave_simulation::wave_equation::PngSequence;
+use wave_simulation::wave_equation::Probe;
+use wave_simulation::wave_equation::PvdSeries;
+use wave_simulation::wave_equation::Replay;
+use wave_simulation::wave_equation::Scenario;
+use wave_simulation::wave_equation::Stencil;
+use wave_simulation::wave_equation::WaveEquation;
+use wave_simulation::wave_equation::WaveEquationConfig;
+use wave_simulation::wave_equation::WaveEquationError;
+use wave_simulation::wave_equation::Y4mWriter;
+use wave_simulation::wave_equation::write_probes_csv;
+
+const USAGE: &str = "\
+usage: headless [options]
+
+  --width N                 nodes along x (default 280)
+  --height N                nodes along y (default 320)
+  --steps N                 number of time steps (default 1000)
+  --h VALUE                 distance between two nodes
+  --delta-t VALUE           time step in s
+  --wave-speed VALUE        wave speed of the homogeneous medium
+  --damping VALUE           damping coefficient in 1/s
+  --stencil NAME            five-point, nine-point or fourth-order
+  --integrator NAME         verlet, leapfrog, rk4 or crank-nicolson
+  --boundary NAME           fixed, free, periodic or absorbing on all edges
+  --threads N               worker threads, needs the feature parallel
+  --scenario FILE           start from a toml scenario or a snapshot, or replay an input recording of the app
+  --impulse X,Y             adds an impulse at a node before the first step, can be repeated
+  --probe X,Y               records the displacement at a node, can be repeated
+  --stats-every N           steps between two lines of statistics (default 10)
+  --png-every N             writes a png frame of the displacement every N steps to the frames directory
+  --colormap NAME           cubehelix, viridis, inferno, magma, plasma, turbo, red-blue or greys (default cubehelix)
+  --range VALUE             auto or the amplitude at the ends of the colormap (default 2.5, as in the app)
+  --gif FILE                writes an animated gif of the displacement
+  --y4m FILE                writes an uncompressed y4m video of the displacement
+  --fps N                   frame rate of the gif and the video (default 25)
+  --stride N                steps between two frames of the gif and the video (default 1)
+  --crop X,Y,W,H            writes only W x H nodes starting at the node X,Y to the gif and the video
+  --scale VALUE             pixels per node of the gif and the video (default 1)
+  --fields LIST             fields of final.npz and the VTK files, comma separated from displacement, velocity,
+                            wave_speed and obstacles (default displacement)
+  --vtk-every N             writes the fields as .vti file every N steps to the vtk directory, with a .pvd collection
+  --output DIR              output directory (default output)
+  --help                    prints this message
+";
+
+// Command line options, unset parameters keep the defaults of the app or the values of the scenario
+struct Options {
+    width: Option<usize>,
+    height: Option<usize>,
+    steps: u64,
+    h: Option<f32>,
+    delta_t: Option<f32>,
+    wave_speed: Option<f32>,
+    damping: Option<f32>,
+    stencil: Option<Stencil>,
+    integrator: Option<Integrator>,
+    boundary: Option<BoundaryCondition>,
+    threads: Option<usize>,
+    scenario: Option<PathBuf>,
+    impulses: Vec<(f32, f32)>,
+    probes: Vec<(f32, f32)>,
+    stats_every: u64,
+    png_every: Option<u64>,
+    colormap: Colormap,
+    range: ColorRange,
+    gif: Option<PathBuf>,
+    y4m: Option<PathBuf>,
+    frame_options: FrameOptions,
+    fields: Vec<Field>,
+    vtk_every: Option<u64>,
+    output: PathBuf,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Options {
+            width: None,
+            height: None,
+            steps: 1000,
+            h: None,
+            delta_t: None,
+            wave_speed: None,
+            damping: None,
+            stencil: None,
+            integrator: None,
+            boundary: None,
+            threads: None,
+            scenario: None,
+            impulses: Vec::new(),
+            probes: Vec::new(),
+            stats_every: 10,
+            png_every: None,
+            colormap: Colormap::Cubehelix,
+            range: ColorRange::default(),
+            gif: None,
+            y4m: None,
+            frame_options: FrameOptions::default(),
+            fields: vec![Field::Displacement],
+            vtk_every: None,
+            output: PathBuf::from("output"),
+        };
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            let value = args.next().with_context(|| format!("missing value of {arg}"))?;
+            let invalid = || format!("invalid value of {arg}: {value}");
+            match arg.as_str() {
+                "--width" => options.width = Some(value.parse().with_context(invalid)?),
+                "--height" => options.height = Some(value.parse().with_context(invalid)?),
+                "--steps" => options.steps = value.parse().with_context(invalid)?,
+                "--h" => options.h = Some(value.parse().with_context(invalid)?),
+                "--delta-t" => options.delta_t = Some(value.parse().with_context(invalid)?),
+                "--wave-speed" => options.wave_speed = Some(value.parse().with_context(invalid)?),
+                "--damping" => options.damping = Some(value.parse().with_context(invalid)?),
+                "--stencil" => options.stencil = Some(match value.as_str() {
+                    "five-point" => Stencil::FivePoint,
+                    "nine-point" => Stencil::NinePoint,
+                    "fourth-order" => Stencil::FourthOrder,
+                    _ => bail!(invalid()),
+                }),
+                "--integrator" => options.integrator = Some(match value.as_str() {
+                    "verlet" => Integrator::Verlet,
+                    "leapfrog" => Integrator::Leapfrog,
+                    "rk4" => Integrator::RungeKutta4,
+                    "crank-nicolson" => Integrator::CrankNicolson,
+                    _ => bail!(invalid()),
+                }),
+                "--boundary" => options.boundary = Some(match value.as_str() {
+                    "fixed" => BoundaryCondition::Fixed,
+                    "free" => BoundaryCondition::Free,
+                    "periodic" => BoundaryCondition::Periodic,
+                    "absorbing" => BoundaryCondition::Absorbing,
+                    _ => bail!(invalid()),
+                }),
+                "--threads" => options.threads = Some(value.parse().with_context(invalid)?),
+                "--scenario" => options.scenario = Some(PathBuf::from(value)),
+                "--impulse" => options.impulses.push(parse_position(&value).with_context(invalid)?),
+                "--probe" => options.probes.push(parse_position(&value).with_context(invalid)?),
+                "--stats-every" => options.stats_every = value.parse::<u64>().with_context(invalid)?.max(1),
+                "--png-every" => options.png_every = Some(value.parse::<u64>().with_context(invalid)?.max(1)),
+                "--colormap" => options.colormap = Colormap::from_name(&value).with_context(invalid)?,
+                "--range" => options.range = match value.as_str() {
+                    "auto" => ColorRange::Auto,
+                    _ => ColorRange::Symmetric(value.parse().with_context(invalid)?),
+                },
+                "--gif" => options.gif = Some(PathBuf::from(value)),
+                "--y4m" => options.y4m = Some(PathBuf::from(value)),
+                "--fps" => options.frame_options.fps = value.parse::<u32>().with_context(invalid)?.max(1),
+                "--stride" => options.frame_options.stride = value.parse::<u64>().with_context(invalid)?.max(1),
+                "--crop" => options.frame_options.crop = Some(parse_crop(&value).with_context(invalid)?),
+                "--scale" => options.frame_options.scale = match value.parse::<f32>().with_context(invalid)? {
+                    scale if scale > 0.0 && scale.is_finite() => scale,
+                    _ => bail!("{}, needs to be a positive finite number", invalid()),
+                },
+                "--fields" => options.fields = value.split(',')
+                    .map(|name| Field::from_name(name.trim()))
+                    .collect::<Option<_>>()
+                    .with_context(invalid)?,
+                "--vtk-every" => options.vtk_every = Some(value.parse::<u64>().with_context(invalid)?.max(1)),
+                "--output" => options.output = PathBuf::from(value),
+                _ => bail!("unknown option {arg}\n\n{USAGE}"),
+            }
+        }
+
+        options.frame_options.colormap = options.colormap;
+        options.frame_options.range = options.range;
+
+        Ok(options)
+    }
+
+    // Replaces the parameters of a new grid which are given on the command line
+    fn config(&self, mut config: WaveEquationConfig) -> WaveEquationConfig {
+        config.width = self.width.unwrap_or(config.width);
+        config.height = self.height.unwrap_or(config.height);
+        config.h = self.h.unwrap_or(config.h);
+        config.delta_t = self.delta_t.unwrap_or(config.delta_t);
+        config.wave_speed = self.wave_speed.unwrap_or(config.wave_speed);
+        config.damping = self.damping.unwrap_or(config.damping);
+        config.stencil = self.stencil.unwrap_or(config.stencil);
+        config.integrator = self.integrator.unwrap_or(config.integrator);
+        config
+    }
+
+    // Parameters which can be changed on an existing grid
+    fn apply(&self, wave_equation: &mut WaveEquation) {
+        if let Some(damping) = self.damping {
+            wave_equation.set_damping(damping);
+        }
+        if let Some(stencil) = self.stencil {
+            wave_equation.set_stencil(stencil);
+        }
+        if let Some(integrator) = self.integrator {
+            wave_equation.set_integrator(integrator);
+        }
+        if let Some(boundary) = self.boundary {
+            wave_equation.set_boundary_conditions(Boundaries::uniform(boundary));
+        }
+        if let Some(threads) = self.threads {
+            wave_equation.set_threads(threads);
+        }
+    }
+}
+
+// Parses a position in nodes given as X,Y
+fn parse_position(value: &str) -> anyhow::Result<(f32, f32)> {
+    let (x, y) = value.split_once(',').context("expected X,Y")?;
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+// Parses a crop region in nodes given as X,Y,W,H
+fn parse_crop(value: &str) -> anyhow::Result<CropRegion> {
+    let values: Vec<usize> = value.split(',').map(|v| v.trim().parse()).collect::<Result<_, _>>()?;
+    match values[..] {
+        [_x, _y, 0, _] | [_x, _y, _, 0] => bail!("the crop region needs at least 1 x 1 nodes"),
+        [x, y, width, height] => Ok(CropRegion { x, y, width, height }),
+        _ => bail!("expected X,Y,W,H"),
+    }
+}
+
+// Builds the simulation with the probes of the scenario, and the recorded input to replay if the scenario is a recording
+fn create(options: &Options) -> anyhow::Result<(WaveEquation, Option<Replay>, Vec<Probe>)> {
+    let path = match &options.scenario {
+        Some(path) => path,
+        None => {
+            let mut wave_equation = WaveEquation::with_config(options.config(WaveEquationConfig::new(280, 320)))?;
+            options.apply(&mut wave_equation);
+            return Ok((wave_equation, None, Vec::new()));
+        },
+    };
+    let bytes = std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let loading = || format!("could not load {}", path.display());
+
+    // the command line overrides the parameters of toml scenarios
+    if !bytes.starts_with(b"WAVEREPL") && !bytes.starts_with(b"WAVESNAP") {
+        let scenario = Scenario::load(path).with_context(loading)?;
+        let mut wave_equation = scenario.build_with_config(options.config(scenario.config())).with_context(loading)?;
+        options.apply(&mut wave_equation);
+        return Ok((wave_equation, None, scenario.probes(options.steps as usize)));
+    }
+
+    if options.width.is_some() || options.height.is_some() || options.h.is_some() ||
+    options.delta_t.is_some() || options.wave_speed.is_some() {
+        bail!("the grid size, h, delta-t and the wave speed are taken from the snapshot {}", path.display());
+    }
+    let (mut wave_equation, replay) = if bytes.starts_with(b"WAVEREPL") {
+        let recording = InputRecording::from_bytes(&bytes).with_context(loading)?;
+        (recording.start()?, Some(Replay::new(recording)))
+    } else {
+        (WaveEquation::from_snapshot_bytes(&bytes).with_context(loading)?, None)
+    };
+    // the changed parameters are checked like with_config checks a new grid
+    if let Some(damping) = options.damping.filter(|damping| !damping.is_finite() || *damping < 0.0) {
+        return Err(WaveEquationError::InvalidParameter { name: "damping", value: damping }.into());
+    }
+    options.apply(&mut wave_equation);
+    wave_equation.check_stability()?;
+
+    Ok((wave_equation, replay, Vec::new()))
+}
+
+// Writes a field as csv, one line per row of the grid starting at y = 0
+fn write_field_csv(path: &PathBuf, field: &[f32], width: usize) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for row in field.chunks(width) {
+        let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+        writeln!(writer, "{}", line.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = Options::parse(std::env::args().skip(1))?;
+    let (mut wave_equation, mut replay, mut probes) = create(&options)?;
+    let (width, height) = (wave_equation.width(), wave_equation.height());
+    for (x, y) in &options.probes {
+        if wave_equation.displacement_at(*x, *y).is_none() {
+            bail!("probe at x = {x}, y = {y} lies outside of the grid of {width} x {height} nodes");
+        }
+    }
+    for (x, y) in &options.impulses {
+        wave_equation.add_impulse(*y, *x);
+        wave_equation.interupt_mouse();
+    }
+
+    std::fs::create_dir_all(&options.output)
+        .with_context(|| format!("could not create {}", options.output.display()))?;
+    let mut stats = BufWriter::new(File::create(options.output.join("stats.csv"))?);
+    writeln!(stats, "step,time,kinetic_energy,potential_energy,total_energy,momentum,mass,max_amplitude,rms")?;
+    probes.extend(options.probes.iter().map(|(x, y)| Probe::new(*x, *y, options.steps as usize)));
+    let mut frames = match options.png_every {
+        Some(every) => {
+            let directory = options.output.join("frames");
+            std::fs::create_dir_all(&directory)
+                .with_context(|| format!("could not create {}", directory.display()))?;
+            let mut frames = PngSequence::new(directory, every, options.colormap, options.range);
+            frames.write(&wave_equation)?;
+            Some(frames)
+        },
+        None => None,
+    };
+    let mut vtk = match options.vtk_every {
+        Some(every) => {
+            let directory = options.output.join("vtk");
+            std::fs::create_dir_all(&directory)
+                .with_context(|| format!("could not create {}", directory.display()))?;
+            let mut vtk = PvdSeries::new(directory, "waves", every, &options.fields);
+            vtk.write(&wave_equation)?;
+            Some(vtk)
+        },
+        None => None,
+    };
+    let mut gif = match &options.gif {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("could not create {}", path.display()))?;
+            let mut gif = GifWriter::new(BufWriter::new(file), options.frame_options)?;
+            gif.write(&wave_equation)?;
+            Some(gif)
+        },
+        None => None,
+    };
+    let mut y4m = match &options.y4m {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("could not create {}", path.display()))?;
+            let mut y4m = Y4mWriter::new(BufWriter::new(file), options.frame_options);
+            y4m.write(&wave_equation)?;
+            Some(y4m)
+        },
+        None => None,
+    };
+
+    println!("{width} x {height} nodes, {} steps", options.steps);
+    let start = Instant::now();
+    for i in 1..=options.steps {
+        // the recorded input is replayed first, the simulation continues without input afterwards
+        let replayed = replay.as_mut().is_some_and(|replay| replay.step(&mut wave_equation));
+        if !replayed {
+            wave_equation.step(None);
+        }
+        probes.iter_mut().for_each(|probe| probe.record(&wave_equation));
+        if let Some(frames) = &mut frames {
+            frames.record(&wave_equation)?;
+        }
+        if let Some(vtk) = &mut vtk {
+            vtk.record(&wave_equation)?;
+        }
+        if let Some(gif) = &mut gif {
+            gif.record(&wave_equation)?;
+        }
+        if let Some(y4m) = &mut y4m {
+            y4m.record(&wave_equation)?;
+        }
+
+        if i % options.stats_every == 0 || i == options.steps {
+            let d = wave_equation.diagnostics();
+            writeln!(stats, "{},{},{},{},{},{},{},{},{}", d.step, wave_equation.time(),
+                d.kinetic_energy, d.potential_energy, d.total_energy, d.momentum, d.mass, d.max_amplitude, d.rms)?;
+        }
+        if let Some(divergence) = wave_equation.divergence() {
+            eprintln!("{divergence}");
+            break;
+        }
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    stats.flush()?;
+
+    write_field_csv(&options.output.join("displacement.csv"), wave_equation.get_current(), width)?;
+    wave_equation.save_snapshot(BufWriter::new(File::create(options.output.join("final.wave"))?))?;
+    wave_equation.write_npz(&options.fields, BufWriter::new(File::create(options.output.join("final.npz"))?))?;
+    if !probes.is_empty() {
+        write_probes_csv(&probes, BufWriter::new(File::create(options.output.join("probes.csv"))?))?;
+    }
+
+    let diagnostics = wave_equation.diagnostics();
+    println!("simulated {} s in {:.2} s, {:.1}M nodes/s", wave_equation.time(), seconds,
+        (width * height) as f64 * wave_equation.steps() as f64 / seconds / 1e6);
+    println!("total energy {}, max amplitude {}", diagnostics.total_energy, diagnostics.max_amplitude);
+    println!("checksum {:016x}", wave_equation.field_checksum());
+    if let Some(frames) = &frames {
+        println!("{} png frames", frames.frames());
+    }
+    if let Some(vtk) = &vtk {
+        println!("{} vtk files", vtk.datasets());
+    }
+    if let Some(gif) = gif {
+        println!("{} gif frames", gif.frames());
+    }
+    if let Some(y4m) = y4m {
+        println!("{} video frames", y4m.frames());
+        y4m.finish()?;
+    }
+    println!("results written to {}", options.output.display());
+
+    if wave_equation.divergence().is_some() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
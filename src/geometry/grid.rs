@@ -6,76 +6,73 @@ use super::super::vertex_color_shader::Color as Color;
 use super::super::vertex_texture_shader::Vertex as VertexTextured;
 use super::super::vertex_heightmap_shader::Heightmap as Heightmap;
 
-pub struct Grid<const M:usize, const N:usize, const MN: usize> {
-    pub vertices: Box<[[Vertex; N]; M]>,
-    pub colors: Box<[[Color; N]; M]>,
+pub struct Grid {
+    width: usize,
+    height: usize,
+
+    pub vertices: Vec<Vertex>,
+    pub colors: Vec<Color>,
     pub indices: Vec<u32>,
 
-    pub vertices_textured: Box<[[VertexTextured; N]; M]>,
+    pub vertices_textured: Vec<VertexTextured>,
 
-    pub heightmap: Box<[[Heightmap; N]; M]>,
+    pub heightmap: Vec<Heightmap>,
 }
 
 #[allow(dead_code)]
-impl<const M:usize, const N:usize, const MN: usize> Grid<M, N, MN> {
-    
-    /// Creates a triangulated 2D grid
-    pub fn new() -> Self {
+impl Grid {
+
+    /// Creates a triangulated 2D grid with width * height nodes, stored row by row
+    pub fn new(width: usize, height: usize) -> Self {
+        let size = width * height;
 
         // create vertices
-        let mut vertices = unsafe {Box::<[[Vertex; N]; M]>::new_zeroed().assume_init()};
-        for y in 0..M {
-            for x in 0..N {
-                vertices[y][x] = Vertex{position: [x as f32, y as f32, 0.0]};
+        let mut vertices = Vec::with_capacity(size);
+        for y in 0..height {
+            for x in 0..width {
+                vertices.push(Vertex{position: [x as f32, y as f32, 0.0]});
             }
         }
 
-        let mut heightmap = unsafe {Box::<[[Heightmap; N]; M]>::new_zeroed().assume_init()};
-        for y in 0..M {
-            for x in 0..N {
-                heightmap[y][x] = Heightmap{height: 0.0};
-            }
-        }
+        let heightmap = vec![Heightmap{height: 0.0}; size];
 
         // create vertices textured
-        let mut vertices_textured = unsafe {Box::<[[VertexTextured; N]; M]>::new_zeroed().assume_init()};
-        for y in 0..M {
-            for x in 0..N {
-                vertices_textured[y][x] = VertexTextured{position: [x as f32, y as f32, 0.0], tex_coords: [x as f32 / N as f32, (M as f32 - y as f32) / M as f32]};
+        let mut vertices_textured = Vec::with_capacity(size);
+        for y in 0..height {
+            for x in 0..width {
+                vertices_textured.push(VertexTextured{position: [x as f32, y as f32, 0.0], tex_coords: [x as f32 / width as f32, (height as f32 - y as f32) / height as f32]});
             }
         }
 
         // create colors
         let color: Color = Color{color: [0.5, 0.5, 0.5]};
-        let mut colors = unsafe {Box::<[[Color; N]; M]>::new_zeroed().assume_init()};
-        for y in 0..M {
-            for x in 0..N {
-                colors[y][x] = color;
-            }
-        }
+        let colors = vec![color; size];
 
         // Triangulate the grid
-        let indices_size: usize = (M-1) * (N-1) * 6;
+        let indices_size: usize = (height-1) * (width-1) * 6;
         let mut indices = vec![0; indices_size];
 
-        for y in 0..M-1 {
-            for x in 0..N-1 {
-                let i = (y * (N-1) + x) * 6;
+        for y in 0..height-1 {
+            for x in 0..width-1 {
+                let i = (y * (width-1) + x) * 6;
 
                 // A, B, C,
-                indices[i  ] = ((y    ) * N + (x    )) as u32;
-                indices[i+1] = ((y    ) * N + (x + 1)) as u32;
-                indices[i+2] = ((y + 1) * N + (x + 1)) as u32;
+                indices[i  ] = ((y    ) * width + (x    )) as u32;
+                indices[i+1] = ((y    ) * width + (x + 1)) as u32;
+                indices[i+2] = ((y + 1) * width + (x + 1)) as u32;
 
                 // C, D, A,
-                indices[i+3] = ((y + 1) * N + (x + 1)) as u32;
-                indices[i+4] = ((y + 1) * N + (x    )) as u32;
-                indices[i+5] = ((y    ) * N + (x    )) as u32;
+                indices[i+3] = ((y + 1) * width + (x + 1)) as u32;
+                indices[i+4] = ((y + 1) * width + (x    )) as u32;
+                indices[i+5] = ((y    ) * width + (x    )) as u32;
             }
         }
 
-        Self { 
-            vertices, 
+        Self {
+            width,
+            height,
+
+            vertices,
             colors,
             indices,
 
@@ -85,35 +82,31 @@ impl<const M:usize, const N:usize, const MN: usize> Grid<M, N, MN> {
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     pub fn vertices_slice(&self) -> &[Vertex] {
-        #[allow(clippy::let_and_return)]
-        let data = unsafe { std::mem::transmute::<&[[Vertex; N]; M],  &[Vertex; MN]>  (&*self.vertices) };
-        
-        data
+        self.vertices.as_slice()
     }
 
     pub fn vertices_textured_slice(&self) -> &[VertexTextured] {
-        #[allow(clippy::let_and_return)]
-        let data = unsafe { std::mem::transmute::<&[[VertexTextured; N]; M],  &[VertexTextured; MN]>  (&*self.vertices_textured) };
-        
-        data
+        self.vertices_textured.as_slice()
     }
 
     pub fn heightmap_slice(&self) -> &[Heightmap] {
-        #[allow(clippy::let_and_return)]
-        let data = unsafe { std::mem::transmute::<&[[Heightmap; N]; M],  &[Heightmap; MN]>  (&*self.heightmap) };
-        
-        data
+        self.heightmap.as_slice()
     }
 
     pub fn colors_slice(&self) -> &[Color] {
-        #[allow(clippy::let_and_return)]
-        let data = unsafe { std::mem::transmute::<&[[Color; N]; M], &[Color; MN]>  (&*self.colors) };
-
-        data
+        self.colors.as_slice()
     }
 
     pub fn indices_slice(&self) -> &[u32] {
         self.indices.as_slice()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,77 @@
+//! Creates line meshes of time series, to be drawn with the lines pipeline
+//!
+
+use super::super::vertex_color_shader::Vertex as Vertex;
+use super::super::vertex_color_shader::Color as Color;
+
+pub struct LineGraph {
+    width: f32,
+    height: f32,
+    capacity: usize,
+    nr_series: usize,
+
+    pub vertices: Vec<Vertex>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<u32>,
+}
+
+#[allow(dead_code)]
+impl LineGraph {
+
+    /// Creates a graph of width * height pixels with one line of capacity points per color
+    pub fn new(colors: &[[f32; 3]], capacity: usize, width: f32, height: f32) -> Self {
+        let nr_series = colors.len();
+        let capacity = capacity.max(2);
+
+        let vertices = vec![Vertex{position: [0.0, 0.0, 0.0]}; nr_series * capacity];
+        let colors = colors.iter()
+            .flat_map(|color| std::iter::repeat_n(Color{color: *color}, capacity))
+            .collect();
+
+        // line list between neighbouring points
+        let mut indices = Vec::with_capacity(nr_series * (capacity - 1) * 2);
+        for series in 0..nr_series {
+            for i in 0..capacity - 1 {
+                let start = (series * capacity + i) as u32;
+                indices.push(start);
+                indices.push(start + 1);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            capacity,
+            nr_series,
+            vertices,
+            colors,
+            indices,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Moves the points to the values of the series, which share one vertical scale
+    /// Series with less than capacity values end at their last value
+    pub fn update(&mut self, series: &[Vec<f32>]) {
+        let values = series.iter().flatten().filter(|value| value.is_finite());
+        let (min, max) = values.fold((f32::MAX, f32::MIN), |(min, max), value| (min.min(*value), max.max(*value)));
+        let range = if max > min { max - min } else { 1.0 };
+
+        let dx = self.width / (self.capacity - 1) as f32;
+        for index in 0..self.nr_series {
+            let values = series.get(index).map(|values| values.as_slice()).unwrap_or(&[]);
+            let values = &values[values.len().saturating_sub(self.capacity)..];
+            for i in 0..self.capacity {
+                let y = match values.get(i.min(values.len().saturating_sub(1))) {
+                    Some(value) if value.is_finite() => (value - min) / range * self.height,
+                    _ => 0.0,
+                };
+                let x = i.min(values.len().saturating_sub(1)) as f32 * dx;
+                self.vertices[index * self.capacity + i] = Vertex{position: [x, y, 0.0]};
+            }
+        }
+    }
+}
@@ -2,5 +2,7 @@
 //!
 
 mod grid;
+mod line_graph;
 
-pub use grid::Grid;
\ No newline at end of file
+pub use grid::Grid;
+pub use line_graph::LineGraph;
\ No newline at end of file
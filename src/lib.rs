@@ -1,10 +1,8 @@
-#![feature(new_uninit)]
-
 //! The main file of the application
 //!
 
 mod geometry;
-mod wave_equation;
+pub mod wave_equation;
 mod mouse_selector;
 mod refraction_shader;
 mod wave_sim_gui;
@@ -36,10 +34,21 @@ use wasm_bindgen::prelude::*;
 
 
 
-const M: usize = 80*4;
-const N: usize = 70*4;
-const MN: usize = M * N;
+// The wave grid has (70 x 80) * GRID_SCALE nodes, the browser uses a smaller grid
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "wasm32")] {
+        const GRID_SCALE: usize = 3;
+    } else {
+        const GRID_SCALE: usize = 4;
+    }
+}
+const MIN_GRID_SCALE: usize = 2;
+const MAX_GRID_SCALE: usize = 6;
 const WAVE_INDEX: usize = 0;    //The index of the wave instance
+const PROBE_COLORS: [[f32; 3]; 4] = [[1.0, 0.3, 0.3], [0.3, 1.0, 0.3], [0.3, 0.6, 1.0], [1.0, 1.0, 0.3]];
+const PROBE_HISTORY_SIZE: usize = 300;
+const QUICK_SAVE_FILE: &str = "quicksave.wave";
+const RECORDING_FILE: &str = "recording.wave_replay";
 
 struct WaveSimulation
 {   
@@ -53,7 +62,7 @@ struct WaveSimulation
     pipeline_lines: vertex_color_shader::Pipeline,
     _texture_bind_group_layout: vertex_texture_shader::TextureBindGroupLayout,
     pipeline_texture_gui: vertex_texture_shader::Pipeline,
-    _heightmap_bind_group_layout: vertex_heightmap_shader::HeightmapBindGroupLayout,
+    heightmap_bind_group_layout: vertex_heightmap_shader::HeightmapBindGroupLayout,
     pipeline_heightmap: vertex_heightmap_shader::Pipeline,
     pipeline_heightmap_color: vertex_heightmap_shader::Pipeline,
 
@@ -72,7 +81,8 @@ struct WaveSimulation
     textures: Vec<vertex_texture_shader::Texture>,
 
     // grid
-    grid_host: geometry::Grid<M, N, MN>,
+    grid_scale: usize,
+    grid_host: geometry::Grid,
     grid_instances: Vec<vertex_color_shader::Instance>,
 
     // grid heightmap
@@ -87,13 +97,25 @@ struct WaveSimulation
     mouse_selector: mouse_selector::MouseSelector,
 
     // simulation
-    wave_equation: wave_equation::WaveEquation<M, N>,
+    wave_equation: wave_equation::WaveEquation,
+    replay: Option<wave_equation::Replay>,
+    preset: Option<wave_equation::Preset>,
 
     // performance monitor
     watch: performance_monitor::Watch<4>,
     graph_host: performance_monitor::Graph,
     graph_device: vertex_color_shader::Mesh,
 
+    // energy diagnostics
+    diagnostics_history: wave_equation::DiagnosticsHistory,
+    diagnostics_graph_host: geometry::LineGraph,
+    diagnostics_graph_device: vertex_color_shader::Mesh,
+
+    // probes, placed with the middle mouse button
+    probes: Vec<wave_equation::Probe>,
+    probe_graph_host: geometry::LineGraph,
+    probe_graph_device: vertex_color_shader::Mesh,
+
     // fps
     fps: performance_monitor::Fps,
 
@@ -149,7 +171,7 @@ impl WaveSimulation
         let yaw = cgmath::Deg(0.0);
         let pitch = cgmath::Deg(0.0);
         let mut camera = renderer::camera::Camera::new(position, yaw, pitch);
-        Self::top_view_point(&mut camera);
+        Self::top_view_point(&mut camera, GRID_SCALE as f32);
 
         let speed = 1.0;
         let sensitivity = 1.0;
@@ -177,37 +199,21 @@ impl WaveSimulation
 
 
 
-        let grid_host: geometry::Grid<M, N, MN> = geometry::Grid::new();
-
-        const INSTANCES: &[vertex_color_shader::Instance] = &[ 
-            vertex_color_shader::Instance{
-                position: glam::Vec3::new(-((N/2) as f32), -((M/2) as f32), 0.0),
-                rotation: glam::Quat::IDENTITY,
-            },
-        ];
+        let (grid_width, grid_height) = Self::grid_size(GRID_SCALE);
+        let grid_host = geometry::Grid::new(grid_width, grid_height);
 
-        let grid_instances = Vec::from(INSTANCES);
+        let grid_instances = vec![Self::grid_instance(grid_width, grid_height)];
 
         //hacked FoV, application appears to use a multiplicator of exactly 1.5
         let mouse_selector = mouse_selector::MouseSelector::new(width, height, (fovy / 2.).tan() * 1.5, grid_instances[WAVE_INDEX]);
 
-        let heightmap = vertex_heightmap_shader::Heightmap2D{
-            data: grid_host.heightmap_slice(),
-            width: N as u32,
-            height: M as u32, 
-        };
-
-        let grid_heightmap_device = vertex_heightmap_shader::Mesh::new(
-            wgpu_renderer.device(),
-            grid_host.vertices_textured_slice(),
-            0, 
-            &heightmap,
-            &heightmap_bind_group_layout,
-            grid_host.indices_slice(),
-            &grid_instances,
-        );
+        let grid_heightmap_device = Self::create_grid_heightmap_device(
+            wgpu_renderer.device(), 
+            &heightmap_bind_group_layout, 
+            &grid_host, 
+            &grid_instances);
 
-        let wave_equation: wave_equation::WaveEquation<M, N> = wave_equation::WaveEquation::new();
+        let wave_equation = wave_equation::WaveEquation::new(grid_width, grid_height);
 
         // performance monitor
         const WATCHPOINTS_SIZE: usize  = 4;
@@ -226,6 +232,44 @@ impl WaveSimulation
             &graph_instances,
         );
 
+        // energy diagnostics, total, kinetic and potential energy
+        const DIAGNOSTICS_HISTORY_SIZE: usize = 300;
+        let diagnostics_history = wave_equation::DiagnosticsHistory::new(DIAGNOSTICS_HISTORY_SIZE);
+        let diagnostics_graph_host = geometry::LineGraph::new(
+            &[[1.0, 1.0, 1.0], [1.0, 0.5, 0.0], [0.0, 0.5, 1.0]], 
+            DIAGNOSTICS_HISTORY_SIZE, 
+            300.0, 
+            100.0);
+        let diagnostics_graph_instance = vertex_color_shader::Instance{
+            position: glam::Vec3::new(10.0, 250.0, 0.0),
+            rotation: glam::Quat::IDENTITY,
+        };
+        let diagnostics_graph_device = vertex_color_shader::Mesh::new(
+            wgpu_renderer.device(),
+            diagnostics_graph_host.vertices.as_slice(),
+            diagnostics_graph_host.colors.as_slice(),
+            diagnostics_graph_host.indices.as_slice(),
+            &[diagnostics_graph_instance],
+        );
+
+        // probes, one trace per color
+        let probe_graph_host = geometry::LineGraph::new(
+            &PROBE_COLORS, 
+            PROBE_HISTORY_SIZE, 
+            300.0, 
+            100.0);
+        let probe_graph_instance = vertex_color_shader::Instance{
+            position: glam::Vec3::new(10.0, 370.0, 0.0),
+            rotation: glam::Quat::IDENTITY,
+        };
+        let probe_graph_device = vertex_color_shader::Mesh::new(
+            wgpu_renderer.device(),
+            probe_graph_host.vertices.as_slice(),
+            probe_graph_host.colors.as_slice(),
+            probe_graph_host.indices.as_slice(),
+            &[probe_graph_instance],
+        );
+
         // image
         let diffuse_bytes = include_bytes!("pony2.png");
         let diffuse_image = image::load_from_memory(diffuse_bytes).unwrap();
@@ -301,7 +345,7 @@ impl WaveSimulation
             pipeline_lines,
             _texture_bind_group_layout: texture_bind_group_layout,
             pipeline_texture_gui,
-            _heightmap_bind_group_layout: heightmap_bind_group_layout,
+            heightmap_bind_group_layout,
             pipeline_heightmap,
             pipeline_heightmap_color,
 
@@ -315,6 +359,7 @@ impl WaveSimulation
             camera_uniform_orthographic,
             camera_uniform_orthographic_buffer,
 
+            grid_scale: GRID_SCALE,
             grid_host,
     
             grid_instances,
@@ -331,11 +376,21 @@ impl WaveSimulation
             mouse_selector,
 
             wave_equation,
+            replay: None,
+            preset: None,
 
             watch,
             graph_host,
             graph_device,
 
+            diagnostics_history,
+            diagnostics_graph_host,
+            diagnostics_graph_device,
+
+            probes: Vec::new(),
+            probe_graph_host,
+            probe_graph_device,
+
             fps,
 
             font,
@@ -351,8 +406,82 @@ impl WaveSimulation
         self.scale_factor = scale_factor;
     }
 
-    fn top_view_point(camera: &mut renderer::camera::Camera) {
-        let position = Point3::new(0.0, 0.0, 67.0*4.0);
+    // width and height of the wave grid for a given scale
+    fn grid_size(grid_scale: usize) -> (usize, usize) {
+        (70 * grid_scale, 80 * grid_scale)
+    }
+
+    // distance of the camera views, grids of any size appear as large as the grid of the same scale
+    fn view_scale(wave_equation: &wave_equation::WaveEquation) -> f32 {
+        (wave_equation.width() as f32 / 70.0).max(wave_equation.height() as f32 / 80.0)
+    }
+
+    // centers the grid around the origin
+    fn grid_instance(grid_width: usize, grid_height: usize) -> vertex_color_shader::Instance {
+        vertex_color_shader::Instance{
+            position: glam::Vec3::new(-((grid_width/2) as f32), -((grid_height/2) as f32), 0.0),
+            rotation: glam::Quat::IDENTITY,
+        }
+    }
+
+    fn create_grid_heightmap_device(device: &wgpu::Device, 
+        heightmap_bind_group_layout: &vertex_heightmap_shader::HeightmapBindGroupLayout,
+        grid_host: &geometry::Grid,
+        grid_instances: &[vertex_color_shader::Instance]) -> vertex_heightmap_shader::Mesh
+    {
+        let heightmap = vertex_heightmap_shader::Heightmap2D{
+            data: grid_host.heightmap_slice(),
+            width: grid_host.width() as u32,
+            height: grid_host.height() as u32, 
+        };
+
+        vertex_heightmap_shader::Mesh::new(
+            device,
+            grid_host.vertices_textured_slice(),
+            0, 
+            &heightmap,
+            heightmap_bind_group_layout,
+            grid_host.indices_slice(),
+            grid_instances,
+        )
+    }
+
+    // Rebuilds the simulation, the mesh and the heightmap texture for a new grid resolution
+    fn set_grid_scale(&mut self, grid_scale: usize) {
+        let (grid_width, grid_height) = Self::grid_size(grid_scale);
+
+        self.grid_scale = grid_scale;
+        self.set_grid(wave_equation::WaveEquation::new(grid_width, grid_height));
+    }
+
+    // Continues with a simulation of any grid size, rebuilds the mesh and the heightmap texture for it
+    fn set_grid(&mut self, wave_equation: wave_equation::WaveEquation) {
+        self.save_running_recording();
+        let (grid_width, grid_height) = (wave_equation.width(), wave_equation.height());
+
+        self.grid_host = geometry::Grid::new(grid_width, grid_height);
+        self.grid_instances = vec![Self::grid_instance(grid_width, grid_height)];
+        self.grid_heightmap_device = Self::create_grid_heightmap_device(
+            self.wgpu_renderer.device(), 
+            &self.heightmap_bind_group_layout, 
+            &self.grid_host, 
+            &self.grid_instances);
+        self.mouse_selector.set_transformation(self.grid_instances[WAVE_INDEX]);
+        self.wave_equation = wave_equation;
+        self.replay = None;
+        self.diagnostics_history.clear();
+        self.probes.clear();
+
+        if self.show_top_viewpoint {
+            Self::top_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
+        }
+        else {
+            Self::side_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
+        }
+    }
+
+    fn top_view_point(camera: &mut renderer::camera::Camera, view_scale: f32) {
+        let position = Point3::new(0.0, 0.0, 67.0 * view_scale);
         let yaw = cgmath::Deg(-90.0).into();
         let pitch = cgmath::Deg(0.0).into();
 
@@ -361,8 +490,8 @@ impl WaveSimulation
         camera.pitch = pitch;
     }
 
-    fn side_view_point(camera: &mut renderer::camera::Camera) {
-        let position = Point3::new(0.0, -(50.0 * 4.0), 55.0);
+    fn side_view_point(camera: &mut renderer::camera::Camera, view_scale: f32) {
+        let position = Point3::new(0.0, -(50.0 * view_scale), 55.0);
         let yaw = cgmath::Deg(-90.0).into();
         let pitch = cgmath::Deg(60.0).into();
 
@@ -415,6 +544,92 @@ impl WaveSimulation
                 self.show_performance_graph = !self.show_performance_graph;
                 true
             },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F3),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                let show_diagnostics = !self.gui.show_diagnostics();
+                self.gui.set_show_diagnostics(show_diagnostics);
+                self.diagnostics_history.clear();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F5),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.export_probes();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F6),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.quick_save();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F7),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.quick_load();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F8),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.toggle_recording();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F9),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.start_replay();
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Back),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                self.probes.clear();
+                true
+            },
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -424,7 +639,7 @@ impl WaveSimulation
                     },
                 ..
             } => { 
-                Self::top_view_point(&mut self.camera);
+                Self::top_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
                 self.show_top_viewpoint = true;
                 true
             },
@@ -437,7 +652,7 @@ impl WaveSimulation
                     },
                 ..
             } => { 
-                Self::side_view_point(&mut self.camera);
+                Self::side_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
                 self.show_top_viewpoint = false;
                 true
             },
@@ -453,6 +668,32 @@ impl WaveSimulation
                 self.show_textured_grid = !self.show_textured_grid;
                 true
             },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Key4),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                let grid_scale = if self.grid_scale >= MAX_GRID_SCALE { MIN_GRID_SCALE } else { self.grid_scale + 1 };
+                self.set_grid_scale(grid_scale);
+                true
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Key5),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => { 
+                let preset = self.preset.map_or(wave_equation::Preset::ALL[0], |preset| preset.next());
+                self.set_preset(preset);
+                true
+            },
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -474,6 +715,15 @@ impl WaveSimulation
                 self.mouse_pressed_camera = *state == ElementState::Pressed;
                 true
             }
+            WindowEvent::MouseInput {
+                button: MouseButton::Middle,
+                state: ElementState::Pressed,
+                ..
+            } => {
+                let (y, x) = self.mouse_selector.mouse_position_on_grid(&self.camera);
+                self.add_probe(x, y);
+                true
+            }
             WindowEvent::MouseInput {
                 button: MouseButton::Left,
                 state,//ElementState::Pressed,
@@ -484,7 +734,7 @@ impl WaveSimulation
 
                 let (consumed, gui_event) = self.gui.mouse_pressed(is_pressed);
                 self.handle_gui_event(gui_event);
-                if !consumed || !is_pressed {
+                if (!consumed || !is_pressed) && self.replay.is_none() {
                     self.mouse_pressed_forces = is_pressed;
                     self.wave_equation.interupt_mouse();
                 }
@@ -538,6 +788,10 @@ impl WaveSimulation
 
                 true
             }
+            WindowEvent::DroppedFile(path) => {
+                self.load_scenario(path);
+                true
+            }
             _ => false,
         }
     }
@@ -551,10 +805,10 @@ impl WaveSimulation
                     wave_sim_gui::ButtonOptionsId::SwitchViewPoint => {
                         self.show_top_viewpoint = !self.show_top_viewpoint;
                         if self.show_top_viewpoint {
-                            Self::top_view_point(&mut self.camera);
+                            Self::top_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
                         }
                         else {
-                            Self::side_view_point(&mut self.camera);
+                            Self::side_view_point(&mut self.camera, Self::view_scale(&self.wave_equation));
                         }
                     },
                     wave_sim_gui::ButtonOptionsId::SwitchTexture => {
@@ -563,19 +817,202 @@ impl WaveSimulation
                     wave_sim_gui::ButtonOptionsId::PerformanceGraph => {
                         self.show_performance_graph = !self.show_performance_graph;
                     },
+                    wave_sim_gui::ButtonOptionsId::Preset(preset) => {
+                        self.set_preset(preset);
+                    },
                 }
             },
             None => {},
         }
     }
 
+    // Places a probe at a grid position, replaces the oldest probe if all colors are used
+    fn add_probe(&mut self, x: f32, y: f32) {
+        if self.wave_equation.displacement_at(x, y).is_none() {
+            return;
+        }
+        if self.probes.len() == PROBE_COLORS.len() {
+            self.probes.remove(0);
+        }
+        self.probes.push(wave_equation::Probe::new(x, y, PROBE_HISTORY_SIZE));
+    }
+
+    // Writes the recorded probe traces to probes.csv in the working directory
+    fn export_probes(&self) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::warn!("probes can not be exported in the browser");
+            }
+            else {
+                let result = std::fs::File::create("probes.csv")
+                    .and_then(|file| wave_equation::write_probes_csv(&self.probes, std::io::BufWriter::new(file)));
+                match result {
+                    Ok(()) => log::info!("exported {} probes to probes.csv", self.probes.len()),
+                    Err(err) => log::warn!("could not export probes: {}", err),
+                }
+            }
+        }
+    }
+
+    // Writes a snapshot of the simulation to the quick save file in the working directory
+    fn quick_save(&self) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::warn!("snapshots can not be saved in the browser");
+            }
+            else {
+                let result = std::fs::File::create(QUICK_SAVE_FILE)
+                    .and_then(|file| self.wave_equation.save_snapshot(std::io::BufWriter::new(file)));
+                match result {
+                    Ok(()) => log::info!("saved snapshot to {}", QUICK_SAVE_FILE),
+                    Err(err) => log::warn!("could not save snapshot: {}", err),
+                }
+            }
+        }
+    }
+
+    // Continues the simulation from the quick save file, switches to the grid size of the snapshot
+    fn quick_load(&mut self) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::warn!("snapshots can not be loaded in the browser");
+            }
+            else {
+                let wave_equation = match std::fs::File::open(QUICK_SAVE_FILE) {
+                    Ok(file) => wave_equation::WaveEquation::load_snapshot(std::io::BufReader::new(file)),
+                    Err(err) => Err(err.into()),
+                };
+                let wave_equation = match wave_equation {
+                    Ok(wave_equation) => wave_equation,
+                    Err(err) => {
+                        log::warn!("could not load snapshot: {}", err);
+                        return;
+                    },
+                };
+
+                self.replace_wave_equation(wave_equation);
+            }
+        }
+    }
+
+    // Continues with a loaded simulation, rebuilds the grid if its size differs
+    fn replace_wave_equation(&mut self, wave_equation: wave_equation::WaveEquation) {
+        self.save_running_recording();
+        let size = (wave_equation.width(), wave_equation.height());
+        if size != (self.wave_equation.width(), self.wave_equation.height()) {
+            self.set_grid(wave_equation);
+            return;
+        }
+        self.wave_equation = wave_equation;
+        self.replay = None;
+        self.diagnostics_history.clear();
+        self.probes.iter_mut().for_each(|probe| probe.clear());
+    }
+
+    // Restarts the simulation with a built-in experiment
+    fn set_preset(&mut self, preset: wave_equation::Preset) {
+        self.preset = Some(preset);
+        self.replay = None;
+        self.wave_equation.apply_preset(preset);
+        self.diagnostics_history.clear();
+        self.probes.iter_mut().for_each(|probe| probe.clear());
+        log::info!("preset {}", preset.name());
+    }
+
+    // Starts the experiment of a scenario file, switches to its grid size and places its probes
+    fn load_scenario(&mut self, path: &std::path::Path) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let _path = path;
+                log::warn!("scenarios can not be loaded in the browser");
+            }
+            else {
+                let result = wave_equation::Scenario::load(path)
+                    .and_then(|scenario| Ok((scenario.build()?, scenario)));
+                let (wave_equation, scenario) = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        log::warn!("could not load {}: {}", path.display(), err);
+                        return;
+                    },
+                };
+                self.replace_wave_equation(wave_equation);
+                self.probes = scenario.probes(PROBE_HISTORY_SIZE);
+                self.probes.truncate(PROBE_COLORS.len());
+                log::info!("loaded scenario {}", path.display());
+            }
+        }
+    }
+
+    // Starts recording the input, or stops and writes the recording to the recording file
+    fn toggle_recording(&mut self) {
+        if !self.wave_equation.is_recording() {
+            self.wave_equation.start_recording();
+            log::info!("recording input");
+            return;
+        }
+        let recording = match self.wave_equation.stop_recording() {
+            Some(recording) => recording,
+            None => return,
+        };
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let _recording = recording;
+                log::warn!("recordings can not be saved in the browser");
+            }
+            else {
+                let result = std::fs::File::create(RECORDING_FILE)
+                    .and_then(|file| recording.save(std::io::BufWriter::new(file)));
+                match result {
+                    Ok(()) => log::info!("saved recording to {}", RECORDING_FILE),
+                    Err(err) => log::warn!("could not save recording: {}", err),
+                }
+            }
+        }
+    }
+
+    // Saves the running recording before the simulation it records is replaced
+    fn save_running_recording(&mut self) {
+        if self.wave_equation.is_recording() {
+            log::warn!("the simulation is replaced, the recording is stopped");
+            self.toggle_recording();
+        }
+    }
+
+    // Replays the recording file from its initial state, one recorded step per frame
+    // A running recording is saved first and then replayed
+    fn start_replay(&mut self) {
+        self.save_running_recording();
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                log::warn!("recordings can not be loaded in the browser");
+            }
+            else {
+                let recording = match std::fs::File::open(RECORDING_FILE) {
+                    Ok(file) => wave_equation::InputRecording::load(std::io::BufReader::new(file)),
+                    Err(err) => Err(err.into()),
+                };
+                let result = recording.and_then(|recording| Ok((recording.start()?, recording)));
+                let (wave_equation, recording) = match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        log::warn!("could not load recording: {}", err);
+                        return;
+                    },
+                };
+                self.replace_wave_equation(wave_equation);
+                self.mouse_pressed_forces = false;
+                self.replay = Some(wave_equation::Replay::new(recording));
+            }
+        }
+    }
+
     fn wave_equation_to_grid_host(&mut self) 
     {
-        for y in 0..M {
-            for x in 0..N {
-                let val = self.wave_equation.get_current()[y][x];
-                self.grid_host.heightmap[y][x].height = val * 1.0;
-            }
+        let current = self.wave_equation.get_current();
+        let obstacles = self.wave_equation.obstacles().cells();
+        for ((heightmap, val), obstacle) in self.grid_host.heightmap.iter_mut().zip(current).zip(obstacles) {
+            heightmap.height = if obstacle.is_some() { refraction_shader::OBSTACLE_MARKER } else { val * 1.0 };
         }
     }
 
@@ -588,15 +1025,31 @@ impl WaveSimulation
 
        // simulation
         // Apply forces
-        if self.mouse_pressed_forces {
+        if self.mouse_pressed_forces && self.replay.is_none() {
             let (y,x) = self.mouse_selector.mouse_position_on_grid(&self.camera);
             self.wave_equation.add_impulse(y, x);
         }
 
-        // calculate simulation step
+        // calculate simulation step, a replay feeds the recorded input instead of the mouse
         self.watch.start(1);
-            self.wave_equation.step(Some(1));
+            match &mut self.replay {
+                Some(replay) => {
+                    if !replay.step(&mut self.wave_equation) {
+                        log::info!("replay finished");
+                        self.replay = None;
+                    }
+                },
+                None => self.wave_equation.step(Some(1)),
+            }
         self.watch.stop(1);
+
+        // start over instead of rendering a blown up simulation
+        if let Some(divergence) = self.wave_equation.divergence() {
+            log::warn!("{}", divergence);
+            self.wave_equation.reset();
+            self.diagnostics_history.clear();
+            self.probes.iter_mut().for_each(|probe| probe.clear());
+        }
         
         // convert to colours
         self.watch.start(2);
@@ -614,6 +1067,30 @@ impl WaveSimulation
         self.watch.update_viewer(&mut self.graph_host);
         self.graph_device.update_vertex_buffer(self.wgpu_renderer.queue(), self.graph_host.vertices.as_slice());
     
+        // energy diagnostics
+        if self.gui.show_diagnostics() {
+            let diagnostics = self.wave_equation.diagnostics();
+            self.diagnostics_history.push(diagnostics);
+            self.gui.set_diagnostics(self.wgpu_renderer.queue(), &self.font, &diagnostics);
+
+            self.diagnostics_graph_host.update(&[
+                self.diagnostics_history.series(|d| d.total_energy),
+                self.diagnostics_history.series(|d| d.kinetic_energy),
+                self.diagnostics_history.series(|d| d.potential_energy),
+            ]);
+            self.diagnostics_graph_device.update_vertex_buffer(self.wgpu_renderer.queue(), self.diagnostics_graph_host.vertices.as_slice());
+        }
+
+        // probes
+        if !self.probes.is_empty() {
+            for probe in &mut self.probes {
+                probe.record(&self.wave_equation);
+            }
+            let series: Vec<Vec<f32>> = self.probes.iter().map(|probe| probe.series()).collect();
+            self.probe_graph_host.update(&series);
+            self.probe_graph_device.update_vertex_buffer(self.wgpu_renderer.queue(), self.probe_graph_host.vertices.as_slice());
+        }
+
         // gui
         self.fps.update(dt);
         self.gui.set_fps(self.wgpu_renderer.queue(), &self.font, self.fps.get());
@@ -671,6 +1148,20 @@ impl WaveSimulation
                 self.graph_device.draw(&mut render_pass);
             }
 
+            // energy diagnostics
+            if self.gui.show_diagnostics() {
+                self.pipeline_lines.bind(&mut render_pass);
+                self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
+                self.diagnostics_graph_device.draw(&mut render_pass);
+            }
+
+            // probes
+            if !self.probes.is_empty() {
+                self.pipeline_lines.bind(&mut render_pass);
+                self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
+                self.probe_graph_device.draw(&mut render_pass);
+            }
+
             // gui
             self.pipeline_texture_gui.bind(&mut render_pass);
             self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
@@ -699,7 +1190,8 @@ pub async fn run()
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
             console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
         } else {
-            env_logger::init();
+            // the status messages of the app are shown without setting RUST_LOG
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error,wave_simulation=info")).init();
         }
     }
 
@@ -734,6 +1226,12 @@ pub async fn run()
 
     let mut state = WaveSimulation::new(&window).await;
 
+    // a scenario file can be passed as the first argument
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = std::env::args_os().nth(1) {
+        state.load_scenario(std::path::Path::new(&path));
+    }
+
     let mut last_render_time = instant::Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
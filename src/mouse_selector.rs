@@ -23,6 +23,11 @@ impl MouseSelector
         self.height = new_height;
     }
 
+    /// updates the transformation of the grid, e.g. after the grid was resized
+    pub fn set_transformation(&mut self, trans: Instance) {
+        self.trans = trans;
+    }
+
     pub fn calc_mouse_position_on_screen(&mut self, x_pos: f32, y_pos: f32) {
         // Invert y
         self.mouse_pos.y = self.height as f32 - y_pos;
@@ -3,6 +3,10 @@
 
 use super::vertex_heightmap_shader;
 
+/// Heightmap value of nodes covered by an obstacle, both shaders draw these nodes as walls
+/// Needs to match OBSTACLE_THRESHOLD in the shaders
+pub const OBSTACLE_MARKER: f32 = 1.0e20;
+
 pub fn create_refraction_pipeline(device: 
     &wgpu::Device, 
     camera_bind_group_layout: &vertex_heightmap_shader::CameraBindGroupLayout, 
@@ -22,6 +22,10 @@ struct InstanceInput {
     @location(8) model_matrix_3: vec4<f32>,
 }
 
+// Heightmap values above the threshold mark obstacles, which are raised to walls
+const OBSTACLE_THRESHOLD: f32 = 1.0e19;
+const WALL_HEIGHT: f32 = 2.0;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
@@ -59,13 +63,17 @@ fn vs_main(
     let index = vec2<u32>(vertex_index % width, vertex_index / width);
 
     let pos_rgb: vec4<f32> = textureLoad(t_heightmap, index, 0);
-    let posz = pos_rgb.r;
-
-    let scale: f32 = clamp((posz * 0.4 + 1.) / 2., 0., 1.);
-    let hsl_color = (1. - scale) * vec3<f32>(260.0, 0.75, 0.35) + scale * vec3<f32>(80.0, 1.5, 0.8);
+    var posz = pos_rgb.r;
 
     var out: VertexOutput;
-    out.color = cubehelix_to_rgb(hsl_color);
+    if posz > OBSTACLE_THRESHOLD {
+        posz = WALL_HEIGHT;
+        out.color = vec3<f32>(0.45, 0.45, 0.45);
+    } else {
+        let scale: f32 = clamp((posz * 0.4 + 1.) / 2., 0., 1.);
+        let hsl_color = (1. - scale) * vec3<f32>(260.0, 0.75, 0.35) + scale * vec3<f32>(80.0, 1.5, 0.8);
+        out.color = cubehelix_to_rgb(hsl_color);
+    }
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position.x, model.position.y, posz, 1.0);
     return out;
 }
@@ -26,8 +26,21 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) reflectivity: f32, // percentage of reflected light, inverse is refracted light
+    @location(2) wall: f32, // 1 on obstacles, 0 on water
 };
 
+// Heightmap values above the threshold mark obstacles, which are raised to walls
+const OBSTACLE_THRESHOLD: f32 = 1.0e19;
+const WALL_HEIGHT: f32 = 2.0;
+
+fn height_at(index: vec2<u32>) -> f32 {
+    let height = textureLoad(t_heightmap, index, 0).r;
+    if height > OBSTACLE_THRESHOLD {
+        return WALL_HEIGHT;
+    }
+    return height;
+}
+
 @vertex 
 fn vs_main(
     @builtin(vertex_index) vertex_index: u32,
@@ -46,19 +59,19 @@ fn vs_main(
     let height = dim.y;
     let index = vec2<u32>(vertex_index % width, vertex_index / width);
 
-    let pos_rgb: vec4<f32> = textureLoad(t_heightmap, index, 0);
-    let posz = pos_rgb.r;
+    let posz = height_at(index);
     var normal = vec3<f32>(0.,0.,1.);   // normal derivatives are 0 at the boundary
     // If not on boundary use negative derivatives to construct normal
     if index.x != 0u && index.x != width - 1u {
-        normal.x = (textureLoad(t_heightmap, index - vec2<u32>(1u,0u), 0).r - textureLoad(t_heightmap, index + vec2<u32>(1u,0u), 0).r)/2.; // -du/dx
+        normal.x = (height_at(index - vec2<u32>(1u,0u)) - height_at(index + vec2<u32>(1u,0u)))/2.; // -du/dx
     }
     if index.y != 0u && index.y != height - 1u {
-        normal.y = (textureLoad(t_heightmap, index - vec2<u32>(0u,1u), 0).r - textureLoad(t_heightmap, index + vec2<u32>(0u,1u), 0).r)/2.; // -du/dy
+        normal.y = (height_at(index - vec2<u32>(0u,1u)) - height_at(index + vec2<u32>(0u,1u)))/2.; // -du/dy
     }
 
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.wall = select(0., 1., textureLoad(t_heightmap, index, 0).r > OBSTACLE_THRESHOLD);
 
     let world_pos = model_matrix * vec4<f32>(model.position.x, model.position.y, posz, 1.0);
     let cam_to_vertex = normalize(world_pos - camera.view_pos).xyz;
@@ -98,7 +111,9 @@ var s_diffuse: sampler;
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let sky_color = vec4<f32>(0.5, 0.5, 1., 1.);
+    let wall_color = vec4<f32>(0.45, 0.45, 0.45, 1.);
     // combine refracted light with some reflected sky (subject to change)
-    return (1. - in.reflectivity) * textureSample(t_diffuse, s_diffuse, in.tex_coords) + 
+    let water_color = (1. - in.reflectivity) * textureSample(t_diffuse, s_diffuse, in.tex_coords) + 
             in.reflectivity * sky_color;
+    return mix(water_color, wall_color, in.wall);
 }
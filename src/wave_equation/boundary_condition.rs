@@ -0,0 +1,421 @@
+//! Boundary conditions at the four edges of the wave grid
+//!
+
+/// Condition applied to the nodes on one edge of the grid
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum BoundaryCondition {
+    /// Dirichlet, the edge is held at zero displacement
+    Fixed,
+    /// Neumann, the normal derivative at the edge is zero
+    Free,
+    /// The edge wraps around to the opposite edge, should be set on both opposite edges
+    Periodic,
+    /// Outgoing waves leave the grid instead of being reflected
+    /// Uses Mur's first order absorbing boundary condition, optionally backed by a sponge layer
+    Absorbing,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Edge {
+    /// x = 0
+    Left,
+    /// x = width - 1
+    Right,
+    /// y = 0
+    Bottom,
+    /// y = height - 1
+    Top,
+}
+
+/// The boundary conditions of all four edges
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct Boundaries {
+    pub left: BoundaryCondition,
+    pub right: BoundaryCondition,
+    pub bottom: BoundaryCondition,
+    pub top: BoundaryCondition,
+}
+
+/// Damping layer along the absorbing edges, soaks up waves which are not perpendicular to the edge
+/// The damping rises quadratically from zero to strength at the edge
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct SpongeLayer {
+    /// thickness in nodes, 0 disables the layer
+    pub thickness: usize,
+    /// damping at the edge in 1/s
+    pub strength: f32,
+}
+
+impl Default for SpongeLayer {
+    fn default() -> Self {
+        Self {
+            thickness: 0,
+            strength: 0.0,
+        }
+    }
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Self::uniform(BoundaryCondition::Free)
+    }
+}
+
+impl Boundaries {
+    /// Applies the same condition to all four edges
+    pub fn uniform(condition: BoundaryCondition) -> Self {
+        Self {
+            left: condition,
+            right: condition,
+            bottom: condition,
+            top: condition,
+        }
+    }
+
+    pub fn get(&self, edge: Edge) -> BoundaryCondition {
+        match edge {
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+            Edge::Bottom => self.bottom,
+            Edge::Top => self.top,
+        }
+    }
+
+    pub fn set(&mut self, edge: Edge, condition: BoundaryCondition) {
+        match edge {
+            Edge::Left => self.left = condition,
+            Edge::Right => self.right = condition,
+            Edge::Bottom => self.bottom = condition,
+            Edge::Top => self.top = condition,
+        }
+    }
+
+    // Index of the node at x, y, which may lie outside of the grid, and the sign of its value
+    // Nodes outside of the grid are ghost nodes mirrored according to the boundary conditions
+    // Returns None for ghost nodes which are zero
+    pub(super) fn resolve_node(&self, width: usize, height: usize, x: isize, y: isize) -> Option<(usize, f32)> {
+        let (x, sign_x) = Self::resolve(x, width, self.left, self.right)?;
+        let (y, sign_y) = Self::resolve(y, height, self.bottom, self.top)?;
+
+        Some((y * width + x, sign_x * sign_y))
+    }
+
+    // Maps an index along one axis into the grid, returns the index and the sign of the ghost value
+    fn resolve(i: isize, len: usize, low: BoundaryCondition, high: BoundaryCondition) -> Option<(usize, f32)> {
+        let len = len as isize;
+        if (0..len).contains(&i) {
+            return Some((i as usize, 1.0));
+        }
+
+        let condition = if i < 0 { low } else { high };
+        let (i, sign) = match condition {
+            // odd extension around the edge node
+            BoundaryCondition::Fixed => (if i < 0 { -i } else { 2 * (len - 1) - i }, -1.0),
+            // even extension around the edge of the cell
+            BoundaryCondition::Free |
+            BoundaryCondition::Absorbing => (if i < 0 { -i - 1 } else { 2 * len - 1 - i }, 1.0),
+            BoundaryCondition::Periodic => (i.rem_euclid(len), 1.0),
+        };
+
+        if (0..len).contains(&i) {
+            Some((i as usize, sign))
+        }
+        else {
+            None
+        }
+    }
+
+    // Overwrites the edge nodes of the next time step where the stencil alone does not apply
+    // The interior nodes of next have to be calculated already, delta_t_h is delta_t / h
+    pub(super) fn apply(&self, current: &[f32], next: &mut [f32], wave_speed: &[f32], width: usize, height: usize, delta_t_h: f32) {
+        // Absorbing, Mur's first order discretization of the one way wave equation u_t + c * u_n = 0
+        // u_b^(n+1) = u_i^n + (c*dt - h) / (c*dt + h) * (u_i^(n+1) - u_b^n)
+        let mur = |i: usize| {
+            let courant = wave_speed[i] * delta_t_h;
+            (courant - 1.0) / (courant + 1.0)
+        };
+        if self.left == BoundaryCondition::Absorbing {
+            for y in 0..height {
+                let i = y * width;
+                next[i] = current[i + 1] + mur(i) * (next[i + 1] - current[i]);
+            }
+        }
+        if self.right == BoundaryCondition::Absorbing {
+            for y in 0..height {
+                let i = y * width + width - 1;
+                next[i] = current[i - 1] + mur(i) * (next[i - 1] - current[i]);
+            }
+        }
+        if self.bottom == BoundaryCondition::Absorbing {
+            for x in 0..width {
+                let i = x;
+                next[i] = current[i + width] + mur(i) * (next[i + width] - current[i]);
+            }
+        }
+        if self.top == BoundaryCondition::Absorbing {
+            for x in 0..width {
+                let i = (height - 1) * width + x;
+                next[i] = current[i - width] + mur(i) * (next[i - width] - current[i]);
+            }
+        }
+
+        // Fixed
+        if self.left == BoundaryCondition::Fixed {
+            for y in 0..height {
+                next[y * width] = 0.0;
+            }
+        }
+        if self.right == BoundaryCondition::Fixed {
+            for y in 0..height {
+                next[y * width + width - 1] = 0.0;
+            }
+        }
+        if self.bottom == BoundaryCondition::Fixed {
+            next[0..width].fill(0.0);
+        }
+        if self.top == BoundaryCondition::Fixed {
+            next[(height - 1) * width..].fill(0.0);
+        }
+    }
+
+    // Damping of the sponge layer for every node of the grid in 1/s, zero away from absorbing edges
+    pub(super) fn sponge_profile(&self, sponge_layer: SpongeLayer, width: usize, height: usize) -> Vec<f32> {
+        let mut profile = vec![0.0; width * height];
+        if sponge_layer.thickness == 0 {
+            return profile;
+        }
+
+        let thickness = sponge_layer.thickness as f32;
+        let damping = |distance: usize| {
+            let depth = (thickness - distance as f32).max(0.0) / thickness;
+            sponge_layer.strength * depth * depth
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sigma: f32 = 0.0;
+                if self.left == BoundaryCondition::Absorbing {
+                    sigma = sigma.max(damping(x));
+                }
+                if self.right == BoundaryCondition::Absorbing {
+                    sigma = sigma.max(damping(width - 1 - x));
+                }
+                if self.bottom == BoundaryCondition::Absorbing {
+                    sigma = sigma.max(damping(y));
+                }
+                if self.top == BoundaryCondition::Absorbing {
+                    sigma = sigma.max(damping(height - 1 - y));
+                }
+                profile[y * width + x] = sigma;
+            }
+        }
+
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::WaveEquation;
+    use super::super::tests::add_bump;
+
+    const WIDTH: usize = 60;
+    const HEIGHT: usize = 40;
+
+    // Places a gaussian ridge at rest, parallel to the y axis
+    fn add_ridge(wave_equation: &mut WaveEquation, x_0: f32) {
+        let width = wave_equation.width();
+        for y in 0..wave_equation.height() {
+            for x in 0..width {
+                let val = (-(x as f32 - x_0).powi(2) / 8.0).exp();
+                wave_equation.current[y * width + x] += val;
+                wave_equation.previous[y * width + x] += val;
+            }
+        }
+    }
+
+    fn energy(field: &[f32]) -> f32 {
+        field.iter().map(|u| u * u).sum()
+    }
+
+    // Discrete energy of the wave, kinetic plus potential part
+    fn wave_energy(wave_equation: &WaveEquation) -> f32 {
+        let width = wave_equation.width();
+        let height = wave_equation.height();
+        let u = &wave_equation.current;
+        let mut energy = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+                let velocity = (u[i] - wave_equation.previous[i]) / wave_equation.delta_t;
+                let u_x = if x + 1 < width { (u[i + 1] - u[i]) / wave_equation.h } else { 0.0 };
+                let u_y = if y + 1 < height { (u[i + width] - u[i]) / wave_equation.h } else { 0.0 };
+                energy += 0.5 * (velocity * velocity + u_x * u_x + u_y * u_y);
+            }
+        }
+        energy
+    }
+
+    fn column(wave_equation: &WaveEquation, x: usize) -> Vec<f32> {
+        let width = wave_equation.width();
+        (0..wave_equation.height()).map(|y| wave_equation.get_current()[y * width + x]).collect()
+    }
+
+    #[test]
+    fn fixed_edges_stay_at_zero() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Fixed));
+        add_bump(&mut wave_equation, 3.0, 3.0);
+
+        for _i in 0..50 {
+            wave_equation.step(None);
+            assert!(column(&wave_equation, 0).iter().all(|u| *u == 0.0));
+            assert!(column(&wave_equation, WIDTH - 1).iter().all(|u| *u == 0.0));
+            assert!(wave_equation.get_current()[0..WIDTH].iter().all(|u| *u == 0.0));
+            assert!(wave_equation.get_current()[(HEIGHT - 1) * WIDTH..].iter().all(|u| *u == 0.0));
+        }
+    }
+
+    #[test]
+    fn fixed_edge_inverts_and_free_edge_keeps_reflection() {
+        let mut fixed = WaveEquation::new(WIDTH, HEIGHT);
+        fixed.set_boundary_condition(Edge::Left, BoundaryCondition::Fixed);
+        let mut free = WaveEquation::new(WIDTH, HEIGHT);
+        free.set_boundary_condition(Edge::Left, BoundaryCondition::Free);
+
+        // a ridge next to the left edge, the left half travels 0.4 nodes per step to the edge and back
+        for wave_equation in [&mut fixed, &mut free] {
+            add_ridge(wave_equation, 12.0);
+            for _i in 0..80 {
+                wave_equation.step(None);
+            }
+        }
+
+        let reflected_fixed: f32 = column(&fixed, 20).iter().sum();
+        let reflected_free: f32 = column(&free, 20).iter().sum();
+        assert!(reflected_fixed < 0.0);
+        assert!(reflected_free > 0.0);
+    }
+
+    #[test]
+    fn free_edges_keep_uniform_field_uniform() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Free));
+        wave_equation.current.fill(1.0);
+        wave_equation.previous.fill(1.0);
+
+        for _i in 0..20 {
+            wave_equation.step(None);
+        }
+
+        let first = wave_equation.get_current()[0];
+        assert!(first != 0.0);
+        assert!(wave_equation.get_current().iter().all(|u| *u == first));
+    }
+
+    #[test]
+    fn periodic_pulse_reenters_on_opposite_side() {
+        let mut periodic = WaveEquation::new(WIDTH, HEIGHT);
+        periodic.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Periodic));
+        let mut fixed = WaveEquation::new(WIDTH, HEIGHT);
+        fixed.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Fixed));
+
+        // a bump close to the right edge
+        for wave_equation in [&mut periodic, &mut fixed] {
+            add_bump(wave_equation, (WIDTH - 4) as f32, 20.0);
+            for _i in 0..40 {
+                wave_equation.step(None);
+            }
+        }
+
+        let left_periodic = energy(&column(&periodic, 4));
+        let left_fixed = energy(&column(&fixed, 4));
+        assert!(left_periodic > 1e-3);
+        assert!(left_fixed < 1e-6);
+    }
+
+    #[test]
+    fn periodic_grid_is_translation_invariant() {
+        let shift = 25;
+        let mut a = WaveEquation::new(WIDTH, HEIGHT);
+        let mut b = WaveEquation::new(WIDTH, HEIGHT);
+        for wave_equation in [&mut a, &mut b] {
+            wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Periodic));
+        }
+        // the bumps are periodic themselves, so only place them far from the edges
+        add_bump(&mut a, 15.0, 20.0);
+        add_bump(&mut b, (15 + shift) as f32, 20.0);
+
+        for _i in 0..60 {
+            a.step(None);
+            b.step(None);
+        }
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let u_a = a.get_current()[y * WIDTH + x];
+                let u_b = b.get_current()[y * WIDTH + (x + shift) % WIDTH];
+                assert!((u_a - u_b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn absorbing_edges_reflect_less_than_free_edges() {
+        let mut absorbing = WaveEquation::new(WIDTH, HEIGHT);
+        absorbing.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Absorbing));
+        let mut free = WaveEquation::new(WIDTH, HEIGHT);
+        free.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Free));
+
+        for wave_equation in [&mut absorbing, &mut free] {
+            add_bump(wave_equation, 30.0, 20.0);
+            for _i in 0..300 {
+                wave_equation.step(None);
+            }
+        }
+
+        assert!(energy(absorbing.get_current()) < 0.5 * energy(free.get_current()));
+    }
+
+    // Fraction of the energy of a gaussian pulse in the middle of the grid, which remains after it hit the edges
+    // The reference has free edges, so the global damping cancels out
+    fn reflected_energy(sponge_layer: SpongeLayer) -> f32 {
+        let mut absorbing = WaveEquation::new(WIDTH, HEIGHT);
+        absorbing.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Absorbing));
+        absorbing.set_sponge_layer(sponge_layer);
+        let mut free = WaveEquation::new(WIDTH, HEIGHT);
+
+        for wave_equation in [&mut absorbing, &mut free] {
+            add_bump(wave_equation, 30.0, 20.0);
+            // the pulse travels 0.4 nodes per step, so it passed the corners after 90 steps
+            for _i in 0..200 {
+                wave_equation.step(None);
+            }
+        }
+
+        wave_energy(&absorbing) / wave_energy(&free)
+    }
+
+    #[test]
+    fn mur_boundary_reflects_little_energy() {
+        let reflected = reflected_energy(SpongeLayer::default());
+        assert!(reflected < 0.01, "reflected energy {reflected}");
+    }
+
+    #[test]
+    fn sponge_layer_reduces_reflected_energy() {
+        let reflected_mur = reflected_energy(SpongeLayer::default());
+        let reflected_sponge = reflected_energy(SpongeLayer { thickness: 16, strength: 8.0 });
+        assert!(reflected_sponge < 0.001, "reflected energy {reflected_sponge}");
+        assert!(reflected_sponge < reflected_mur);
+    }
+}
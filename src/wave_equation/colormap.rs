@@ -0,0 +1,238 @@
+//! Colors the displacement on the cpu, to write images of the simulation without a window or a GPU
+//!
+//! The cubehelix colormap with the default range gives the same colors as the color shader of the app.
+//!
+
+use std::path::PathBuf;
+
+use super::WaveEquation;
+
+// Color of the nodes covered by an obstacle, same as in shader_color.wgsl
+const OBSTACLE_COLOR: [f32; 3] = [0.45, 0.45, 0.45];
+
+// Ends of the cubehelix colormap as hue in degrees, saturation and lightness, same as in shader_color.wgsl
+const CUBEHELIX_START: [f32; 3] = [260.0, 0.75, 0.35];
+const CUBEHELIX_END: [f32; 3] = [80.0, 1.5, 0.8];
+
+/// Maps a value between 0 and 1 to a color
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Colormap {
+    /// Blue to green, the colors of the app
+    Cubehelix,
+    Viridis,
+    Inferno,
+    Magma,
+    Plasma,
+    Turbo,
+    /// Diverging from red over white to blue
+    RedBlue,
+    Greys,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 8] = [
+        Colormap::Cubehelix,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Magma,
+        Colormap::Plasma,
+        Colormap::Turbo,
+        Colormap::RedBlue,
+        Colormap::Greys,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Cubehelix => "cubehelix",
+            Colormap::Viridis => "viridis",
+            Colormap::Inferno => "inferno",
+            Colormap::Magma => "magma",
+            Colormap::Plasma => "plasma",
+            Colormap::Turbo => "turbo",
+            Colormap::RedBlue => "red-blue",
+            Colormap::Greys => "greys",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|colormap| colormap.name() == name)
+    }
+
+    /// Color of a value between 0 and 1 as rgb, values outside are clamped
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        let t = if t.is_nan() { 0.5 } else { t.clamp(0.0, 1.0) };
+        let gradient = match self {
+            Colormap::Cubehelix => {
+                let c: Vec<f32> = CUBEHELIX_START.iter().zip(CUBEHELIX_END)
+                    .map(|(start, end)| (1.0 - t) * start + t * end)
+                    .collect();
+                return to_rgb8(cubehelix_to_rgb(c[0], c[1], c[2]));
+            },
+            Colormap::Viridis => colorous::VIRIDIS,
+            Colormap::Inferno => colorous::INFERNO,
+            Colormap::Magma => colorous::MAGMA,
+            Colormap::Plasma => colorous::PLASMA,
+            Colormap::Turbo => colorous::TURBO,
+            Colormap::RedBlue => colorous::RED_BLUE,
+            Colormap::Greys => colorous::GREYS,
+        };
+        let color = gradient.eval_continuous(t as f64);
+        [color.r, color.g, color.b]
+    }
+}
+
+// Same as cubehelix_to_rgb in shader_color.wgsl
+fn cubehelix_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let h = (hue + 120.0).to_radians();
+    let l = lightness;
+    let a = saturation * l * (1.0 - l);
+    let cos_h = h.cos();
+    let sin_h = h.sin();
+    let r = (l - a * (0.14861 * cos_h - 1.78277 * sin_h)).min(1.0);
+    let g = (l - a * (0.29227 * cos_h + 0.90649 * sin_h)).min(1.0);
+    let b = (l + a * (1.97294 * cos_h)).min(1.0);
+    [r, g, b]
+}
+
+// Converts like the GPU does when writing to a 8 bit render target
+fn to_rgb8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Displacements which are mapped to the ends of the colormap
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum ColorRange {
+    /// From -amplitude to amplitude, a displacement of zero maps to the middle of the colormap
+    Symmetric(f32),
+    /// From the lowest to the highest displacement of each frame
+    Auto,
+}
+
+impl Default for ColorRange {
+    /// Range of the color shader of the app
+    fn default() -> Self {
+        ColorRange::Symmetric(2.5)
+    }
+}
+
+impl WaveEquation {
+    /// Colors the current displacement, obstacles are grey
+    /// The rows are flipped, so that y points up like in the top view of the app
+    pub fn to_image(&self, colormap: Colormap, range: ColorRange) -> image::RgbaImage {
+        let current = self.get_current();
+        let obstacles = self.obstacles.cells();
+        let (low, high) = match range {
+            ColorRange::Symmetric(amplitude) => (-amplitude, amplitude),
+            ColorRange::Auto => current.iter().zip(obstacles)
+                .filter(|(_u, obstacle)| obstacle.is_none())
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), (u, _obstacle)| (low.min(*u), high.max(*u))),
+        };
+        // an even field is drawn with the middle color
+        let normalize = |u: f32| if high > low { (u - low) / (high - low) } else { 0.5 };
+
+        let obstacle_color = to_rgb8(OBSTACLE_COLOR);
+        image::RgbaImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let index = (self.height - 1 - y as usize) * self.width + x as usize;
+            let [r, g, b] = match obstacles[index] {
+                Some(_) => obstacle_color,
+                None => colormap.color(normalize(current[index])),
+            };
+            image::Rgba([r, g, b, 255])
+        })
+    }
+}
+
+/// Writes the displacement as numbered png files every few steps, e.g. to make figures or animations
+pub struct PngSequence {
+    directory: PathBuf,
+    every: u64,
+    colormap: Colormap,
+    range: ColorRange,
+    frames: usize,
+}
+
+impl PngSequence {
+    /// Writes into an existing directory every given number of steps
+    pub fn new(directory: impl Into<PathBuf>, every: u64, colormap: Colormap, range: ColorRange) -> Self {
+        Self {
+            directory: directory.into(),
+            every: every.max(1),
+            colormap,
+            range,
+            frames: 0,
+        }
+    }
+
+    /// Writes a frame if the number of steps is a multiple of every, returns if it did
+    pub fn record(&mut self, wave_equation: &WaveEquation) -> image::ImageResult<bool> {
+        if !wave_equation.steps().is_multiple_of(self.every) {
+            return Ok(false);
+        }
+        self.write(wave_equation)?;
+        Ok(true)
+    }
+
+    /// Writes the next frame, named after the step, e.g. frame_000120.png
+    pub fn write(&mut self, wave_equation: &WaveEquation) -> image::ImageResult<()> {
+        let path = self.directory.join(format!("frame_{:06}.png", wave_equation.steps()));
+        wave_equation.to_image(self.colormap, self.range).save_with_format(path, image::ImageFormat::Png)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Obstacle;
+
+    #[test]
+    fn cubehelix_matches_the_color_shader() {
+        // values of shader_color.wgsl at the ends and in the middle
+        assert_eq!(Colormap::Cubehelix.color(0.0), to_rgb8(cubehelix_to_rgb(260.0, 0.75, 0.35)));
+        assert_eq!(Colormap::Cubehelix.color(0.5), to_rgb8(cubehelix_to_rgb(170.0, 1.125, 0.575)));
+        assert_eq!(Colormap::Cubehelix.color(1.0), [175, 240, 91]);
+        assert_eq!(Colormap::Cubehelix.color(0.0), [110, 64, 170]);
+        assert_eq!(Colormap::Cubehelix.color(2.0), Colormap::Cubehelix.color(1.0));
+
+        // the shader maps the displacement u to (u * 0.4 + 1) / 2
+        let mut wave_equation = WaveEquation::new(4, 3);
+        wave_equation.set_initial_displacement(&[0.0, 1.0, -1.0, 3.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -2.5]);
+        wave_equation.obstacles_mut().set(1, 1, Some(Obstacle::Fixed));
+        let image = wave_equation.to_image(Colormap::Cubehelix, ColorRange::default());
+        let color = |x: u32, y: u32| {
+            let image::Rgba([r, g, b, a]) = *image.get_pixel(x, 2 - y);
+            assert_eq!(a, 255);
+            [r, g, b]
+        };
+        for (x, y, u) in [(0, 0, 0.0), (1, 0, 1.0), (2, 0, -1.0), (3, 0, 3.0), (3, 2, -2.5)] {
+            assert_eq!(color(x, y), Colormap::Cubehelix.color((u * 0.4 + 1.0) / 2.0));
+        }
+        assert_eq!(color(1, 1), [115, 115, 115]);
+    }
+
+    #[test]
+    fn auto_range_spans_the_colormap() {
+        let mut wave_equation = WaveEquation::new(3, 3);
+        wave_equation.set_initial_displacement(&[0.2, 0.3, 0.6, 0.2, 0.2, 0.2, 0.2, 0.2, 0.2]);
+        let image = wave_equation.to_image(Colormap::Greys, ColorRange::Auto);
+        assert_eq!(image.get_pixel(0, 2).0[..3], Colormap::Greys.color(0.0));
+        assert_eq!(image.get_pixel(1, 2).0[..3], Colormap::Greys.color(0.25));
+        assert_eq!(image.get_pixel(2, 2).0[..3], Colormap::Greys.color(1.0));
+
+        let even = WaveEquation::new(3, 3).to_image(Colormap::Viridis, ColorRange::Auto);
+        assert!(even.pixels().all(|pixel| pixel.0[..3] == Colormap::Viridis.color(0.5)));
+
+        assert_eq!(Colormap::from_name("red-blue"), Some(Colormap::RedBlue));
+        assert_eq!(Colormap::from_name("jet"), None);
+    }
+}
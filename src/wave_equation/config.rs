@@ -0,0 +1,45 @@
+//! Parameters to create a wave equation
+//!
+
+use super::Integrator;
+use super::Stencil;
+
+/// Grid size and physical parameters of a wave equation
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct WaveEquationConfig {
+    pub width: usize,
+    pub height: usize,
+    /// distance between two nodes
+    pub h: f32,
+    /// time simulated by one call to step
+    pub delta_t: f32,
+    /// wave speed of the homogeneous medium
+    pub wave_speed: f32,
+    /// damping coefficient in 1/s
+    pub damping: f32,
+    /// discretization of the laplacian
+    pub stencil: Stencil,
+    /// time integration scheme
+    pub integrator: Integrator,
+    /// displacements above this value are treated as a blow up of the simulation
+    pub max_amplitude: f32,
+}
+
+impl WaveEquationConfig {
+    /// The default parameters of the app for a grid with width * height nodes
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            h: 0.125,
+            delta_t: 0.05,
+            wave_speed: 1.0,
+            damping: 0.08, // lets the waves decay by about 0.2% per time step
+            stencil: Stencil::FivePoint,
+            integrator: Integrator::Verlet,
+            max_amplitude: 1.0e6,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Energy and conservation diagnostics, to check whether the simulation is physically sane
+//!
+//! The grid is treated as a membrane with unit tension, so a node with wave speed c has the density 1/c^2.
+//!
+
+use std::collections::VecDeque;
+
+use super::Integrator;
+use super::WaveEquation;
+
+/// Integral quantities of the grid at one time step
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct Diagnostics {
+    /// number of calls to step
+    pub step: u64,
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub total_energy: f32,
+    /// integral of the density times the velocity
+    pub momentum: f32,
+    /// integral of the displacement
+    pub mass: f32,
+    /// largest absolute displacement
+    pub max_amplitude: f32,
+    /// root mean square of the displacement
+    pub rms: f32,
+}
+
+impl WaveEquation {
+    // Velocity of all nodes, the Verlet scheme derives it from the last two time levels
+    pub(super) fn velocity_field(&self) -> Vec<f32> {
+        match self.integrator {
+            Integrator::Verlet => self.current.iter().zip(&self.previous)
+                .map(|(u, u_old)| (u - u_old) / self.substep_delta_t)
+                .collect(),
+            _ => self.velocity.clone(),
+        }
+    }
+
+    /// Kinetic energy 1/2 * integral of v^2 / c^2
+    /// The Verlet and the Leapfrog scheme evaluate it half a time step behind the displacement
+    pub fn kinetic_energy(&self) -> f32 {
+        let velocity = self.velocity_field();
+        let h2 = self.h * self.h;
+        velocity.iter().zip(self.medium.wave_speed())
+            .map(|(v, c)| 0.5 * v * v / (c * c) * h2)
+            .sum()
+    }
+
+    /// Potential energy 1/2 * integral of |grad u|^2
+    /// The Verlet and the Leapfrog scheme use the product of the last two time levels, which they conserve exactly
+    pub fn potential_energy(&self) -> f32 {
+        let per_node_rows = self.per_node_rows();
+        let mut laplacian = vec![0.0; self.width * self.height];
+        let other = match self.integrator {
+            Integrator::Verlet | Integrator::Leapfrog => &self.previous,
+            Integrator::RungeKutta4 | Integrator::CrankNicolson => &self.current,
+        };
+        self.laplacian_field(other, &mut laplacian, &per_node_rows);
+
+        // the area h^2 of a node cancels with the 1/h^2 of the laplacian
+        self.current.iter().zip(&laplacian).zip(self.obstacles.cells())
+            .filter(|(_, cell)| cell.is_none())
+            .map(|((u, laplacian), _)| -0.5 * u * laplacian)
+            .sum()
+    }
+
+    /// Sum of the kinetic and the potential energy, constant without damping, forces and absorbing edges
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy() + self.potential_energy()
+    }
+
+    /// Integral of v / c^2
+    pub fn momentum(&self) -> f32 {
+        let velocity = self.velocity_field();
+        let h2 = self.h * self.h;
+        velocity.iter().zip(self.medium.wave_speed())
+            .map(|(v, c)| v / (c * c) * h2)
+            .sum()
+    }
+
+    /// Integral of the displacement
+    pub fn mass(&self) -> f32 {
+        let h2 = self.h * self.h;
+        self.current.iter().map(|u| u * h2).sum()
+    }
+
+    /// Largest absolute displacement
+    pub fn max_displacement(&self) -> f32 {
+        self.current.iter().fold(0.0, |max, u| max.max(u.abs()))
+    }
+
+    /// Root mean square of the displacement
+    pub fn rms(&self) -> f32 {
+        let sum: f32 = self.current.iter().map(|u| u * u).sum();
+        (sum / self.current.len() as f32).sqrt()
+    }
+
+    /// Calculates all diagnostics of the current time step
+    pub fn diagnostics(&self) -> Diagnostics {
+        let kinetic_energy = self.kinetic_energy();
+        let potential_energy = self.potential_energy();
+
+        Diagnostics {
+            step: self.steps,
+            kinetic_energy,
+            potential_energy,
+            total_energy: kinetic_energy + potential_energy,
+            momentum: self.momentum(),
+            mass: self.mass(),
+            max_amplitude: self.max_displacement(),
+            rms: self.rms(),
+        }
+    }
+}
+
+/// Time series of the diagnostics, keeps the latest entries up to the capacity
+pub struct DiagnosticsHistory {
+    capacity: usize,
+    entries: VecDeque<Diagnostics>,
+}
+
+impl DiagnosticsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends an entry, drops the oldest one if the history is full
+    pub fn push(&mut self, diagnostics: Diagnostics) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(diagnostics);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn latest(&self) -> Option<&Diagnostics> {
+        self.entries.back()
+    }
+
+    /// Iterates from the oldest to the latest entry
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostics> {
+        self.entries.iter()
+    }
+
+    /// Extracts one quantity, e.g. series(|d| d.total_energy)
+    pub fn series(&self, quantity: impl Fn(&Diagnostics) -> f32) -> Vec<f32> {
+        self.entries.iter().map(quantity).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Shape;
+    use super::super::WaveEquationConfig;
+
+    const WIDTH: usize = 50;
+    const HEIGHT: usize = 40;
+
+    // Starts a gaussian bump at rest in a medium with a slow region
+    fn wave_equation(integrator: Integrator, damping: f32) -> WaveEquation {
+        let mut config = WaveEquationConfig::new(WIDTH, HEIGHT);
+        config.damping = damping;
+        config.integrator = integrator;
+        let mut wave_equation = WaveEquation::with_config(config).unwrap();
+        wave_equation.medium_mut().fill(Shape::Circle { x: 30.0, y: 20.0, radius: 8.0 }, 0.5);
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let r2 = (x as f32 - 20.0).powi(2) + (y as f32 - 20.0).powi(2);
+                let val = (-r2 / 8.0).exp();
+                wave_equation.current[y * WIDTH + x] = val;
+                wave_equation.previous[y * WIDTH + x] = val;
+            }
+        }
+        wave_equation
+    }
+
+    #[test]
+    fn energy_is_conserved_without_damping() {
+        // RK4 loses a little energy on the short waves
+        for (integrator, tolerance) in [
+            (Integrator::Verlet, 1e-3),
+            (Integrator::Leapfrog, 1e-3),
+            (Integrator::RungeKutta4, 2e-2),
+            (Integrator::CrankNicolson, 1e-3)] {
+            let mut wave_equation = wave_equation(integrator, 0.0);
+            let initial = wave_equation.total_energy();
+            assert!(initial > 0.0);
+
+            for _i in 0..300 {
+                wave_equation.step(None);
+                let energy = wave_equation.diagnostics();
+                assert!(((energy.total_energy - initial) / initial).abs() < tolerance,
+                    "{integrator:?} energy {} initial {initial}", energy.total_energy);
+            }
+            // the energy is moving between the kinetic and the potential part
+            assert!(wave_equation.kinetic_energy() > 0.1 * initial);
+        }
+    }
+
+    #[test]
+    fn energy_decays_with_damping() {
+        let mut wave_equation = wave_equation(Integrator::Verlet, 0.5);
+        let initial = wave_equation.total_energy();
+        for _i in 0..100 {
+            wave_equation.step(None);
+        }
+        // the energy decays with exp(-damping * t)
+        let expected = initial * (-0.5 * 100.0 * 0.05_f32).exp();
+        assert!((wave_equation.total_energy() / expected - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn statistics_of_uniform_displacement() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.current.fill(0.5);
+        wave_equation.previous.fill(0.5);
+        let diagnostics = wave_equation.diagnostics();
+
+        let area = (WIDTH * HEIGHT) as f32 * wave_equation.h * wave_equation.h;
+        assert!((diagnostics.mass - 0.5 * area).abs() < 1e-4);
+        assert_eq!(diagnostics.momentum, 0.0);
+        assert_eq!(diagnostics.max_amplitude, 0.5);
+        assert!((diagnostics.rms - 0.5).abs() < 1e-6);
+        assert!(diagnostics.total_energy.abs() < 1e-6);
+    }
+
+    #[test]
+    fn history_keeps_latest_entries() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let mut history = DiagnosticsHistory::new(4);
+        for _i in 0..6 {
+            wave_equation.step(None);
+            history.push(wave_equation.diagnostics());
+        }
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.series(|d| d.step as f32), vec![3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(history.latest().unwrap().step, 6);
+    }
+}
@@ -0,0 +1,175 @@
+//! Errors of the wave equation
+//!
+
+use std::fmt;
+
+use super::CropRegion;
+
+/// Reason why a wave equation could not be created
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum WaveEquationError {
+    /// The grid needs at least 3 x 3 nodes
+    InvalidGridSize { width: usize, height: usize },
+    /// A parameter is not a positive finite number
+    InvalidParameter { name: &'static str, value: f32 },
+    /// The courant number c * delta_t / h exceeds the stability limit of the scheme
+    Unstable { courant_number: f32, limit: f32 },
+}
+
+impl fmt::Display for WaveEquationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveEquationError::InvalidGridSize { width, height } =>
+                write!(f, "invalid grid size {width} x {height}, at least 3 x 3 nodes are needed"),
+            WaveEquationError::InvalidParameter { name, value } =>
+                write!(f, "invalid parameter {name} = {value}, needs to be a positive finite number"),
+            WaveEquationError::Unstable { courant_number, limit } =>
+                write!(f, "unstable parameters, the courant number c * delta_t / h = {courant_number} exceeds the limit {limit}"),
+        }
+    }
+}
+
+impl std::error::Error for WaveEquationError {}
+
+/// Diagnostic of a simulation which blew up
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct Divergence {
+    /// number of the call to step
+    pub step: u64,
+    pub substep: usize,
+    /// first offending node
+    pub x: usize,
+    pub y: usize,
+    pub value: f32,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "simulation diverged in step {} (substep {}), value {} at node x = {}, y = {}",
+            self.step, self.substep, self.value, self.x, self.y)
+    }
+}
+
+/// Reason why a snapshot could not be loaded
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// The data does not start with the magic bytes of a snapshot
+    InvalidMagic,
+    /// The snapshot was written by a newer version of the format
+    UnsupportedVersion(u32),
+    /// The payload does not match the checksum of the header
+    ChecksumMismatch,
+    /// The data ends before the snapshot is complete
+    Truncated,
+    /// A value is out of its valid range, e.g. an unknown enum tag
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "could not read snapshot: {err}"),
+            SnapshotError::InvalidMagic => write!(f, "not a snapshot file"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {version}"),
+            SnapshotError::ChecksumMismatch => write!(f, "snapshot is corrupted, the checksum does not match"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::InvalidValue(name) => write!(f, "snapshot contains an invalid {name}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+/// Reason why a scenario could not be loaded, lines and columns start at 1
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    /// The file is no valid toml or does not match the format, e.g. an unknown key or a value of the wrong type
+    Syntax { line: usize, column: usize, message: String },
+    /// A value is out of its valid range, field is the path of the key, e.g. point_sources[0].frequency
+    InvalidValue { line: usize, column: usize, field: String, message: String },
+    /// The grid parameters are invalid after they were changed, e.g. on the command line
+    Parameters(WaveEquationError),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "could not read scenario: {err}"),
+            ScenarioError::Syntax { line, column, message } => write!(f, "line {line}, column {column}: {message}"),
+            ScenarioError::InvalidValue { line, column, field, message } =>
+                write!(f, "line {line}, column {column}: invalid value of {field}, {message}"),
+            ScenarioError::Parameters(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScenarioError::Io(err) => Some(err),
+            ScenarioError::Parameters(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(err: std::io::Error) -> Self {
+        ScenarioError::Io(err)
+    }
+}
+
+/// Reason why frames of a gif or a video can not be written with the frame options
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum FrameError {
+    /// The crop region has no nodes or starts outside of the grid
+    InvalidCrop(CropRegion),
+    /// The scale is not a positive finite number
+    InvalidScale(f32),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::InvalidCrop(crop) => write!(f, "invalid crop region of {} x {} nodes at x = {}, y = {}, it needs to cover nodes of the grid",
+                crop.width, crop.height, crop.x, crop.y),
+            FrameError::InvalidScale(scale) => write!(f, "invalid scale {scale}, needs to be a positive finite number"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<FrameError> for image::ImageError {
+    fn from(err: FrameError) -> Self {
+        image::ImageError::Parameter(image::error::ParameterError::from_kind(
+            image::error::ParameterErrorKind::Generic(err.to_string())))
+    }
+}
+
+impl From<FrameError> for std::io::Error {
+    fn from(err: FrameError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+    }
+}
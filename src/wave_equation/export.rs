@@ -0,0 +1,372 @@
+//! Exports fields of the grid for the analysis in other tools, as NumPy .npy and .npz files and as VTK image data
+//!
+//! Arrays are float32 with the shape (height, width), so that array[y][x] is the node x, y.
+//! VTK files place the nodes h apart and can be loaded into ParaView, a .pvd collection lists the files of a run.
+//!
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::WaveEquation;
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+// numpy pads the header so that the data starts at a multiple of 64 bytes
+const NPY_ALIGNMENT: usize = 64;
+
+/// Field of the grid which can be exported
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Field {
+    Displacement,
+    Velocity,
+    WaveSpeed,
+    /// 1 where an obstacle covers the node, 0 elsewhere
+    Obstacles,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [
+        Field::Displacement,
+        Field::Velocity,
+        Field::WaveSpeed,
+        Field::Obstacles,
+    ];
+
+    /// Name of the array in .npz and VTK files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Displacement => "displacement",
+            Field::Velocity => "velocity",
+            Field::WaveSpeed => "wave_speed",
+            Field::Obstacles => "obstacles",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+}
+
+impl WaveEquation {
+    /// Values of a field row by row
+    pub fn field(&self, field: Field) -> Vec<f32> {
+        match field {
+            Field::Displacement => self.current.clone(),
+            Field::Velocity => self.velocity_field(),
+            Field::WaveSpeed => self.medium.wave_speed().to_vec(),
+            Field::Obstacles => self.obstacles.cells().iter()
+                .map(|cell| if cell.is_some() { 1.0 } else { 0.0 })
+                .collect(),
+        }
+    }
+
+    /// Writes a field as .npy file, which numpy.load reads
+    pub fn write_npy(&self, field: Field, writer: impl Write) -> std::io::Result<()> {
+        write_npy(writer, &self.field(field), &[self.height, self.width])
+    }
+
+    /// Writes the fields as uncompressed .npz archive with one array per field, named after the field
+    pub fn write_npz(&self, fields: &[Field], writer: impl Write) -> std::io::Result<()> {
+        let mut zip = ZipWriter::new(writer);
+        for field in fields {
+            let mut npy = Vec::new();
+            self.write_npy(*field, &mut npy)?;
+            zip.add_file(&format!("{}.npy", field.name()), &npy)?;
+        }
+        zip.finish()
+    }
+
+    /// Writes the fields as point data of a VTK .vti file
+    pub fn write_vti(&self, fields: &[Field], mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "<?xml version=\"1.0\"?>")?;
+        writeln!(writer, "<VTKFile type=\"ImageData\" version=\"1.0\" byte_order=\"LittleEndian\">")?;
+        let extent = format!("0 {} 0 {} 0 0", self.width - 1, self.height - 1);
+        writeln!(writer, "  <ImageData WholeExtent=\"{extent}\" Origin=\"0 0 0\" Spacing=\"{} {} {}\">", self.h, self.h, self.h)?;
+        writeln!(writer, "    <Piece Extent=\"{extent}\">")?;
+        let scalars = fields.first().map_or("", |field| field.name());
+        writeln!(writer, "      <PointData Scalars=\"{scalars}\">")?;
+        for field in fields {
+            writeln!(writer, "        <DataArray type=\"Float32\" Name=\"{}\" format=\"ascii\">", field.name())?;
+            for row in self.field(*field).chunks(self.width) {
+                let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                writeln!(writer, "          {}", line.join(" "))?;
+            }
+            writeln!(writer, "        </DataArray>")?;
+        }
+        writeln!(writer, "      </PointData>")?;
+        writeln!(writer, "    </Piece>")?;
+        writeln!(writer, "  </ImageData>")?;
+        writeln!(writer, "</VTKFile>")?;
+        writer.flush()
+    }
+}
+
+/// Writes an array of float32 in the .npy format version 1.0, the shape is in row major order
+pub fn write_npy(mut writer: impl Write, data: &[f32], shape: &[usize]) -> std::io::Result<()> {
+    let shape: Vec<String> = shape.iter().map(|len| len.to_string()).collect();
+    // a tuple with one element needs a trailing comma
+    let shape = if shape.len() == 1 { format!("{},", shape[0]) } else { shape.join(", ") };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({shape}), }}");
+    let len = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.extend(std::iter::repeat_n(' ', len.next_multiple_of(NPY_ALIGNMENT) - len));
+    header.push('\n');
+
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_le_bytes()).collect();
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Reads an array of float32 written by write_npy, returns the shape and the data
+pub fn read_npy(bytes: &[u8]) -> std::io::Result<(Vec<usize>, Vec<f32>)> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid npy file: {message}"));
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC || bytes[6] != 1 {
+        return Err(invalid("expected the magic bytes of version 1"));
+    }
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let header = bytes.get(10..10 + header_len)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("truncated header"))?;
+    if !header.contains("'descr': '<f4'") || !header.contains("'fortran_order': False") {
+        return Err(invalid("expected float32 in row major order"));
+    }
+
+    let shape = header.split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .ok_or_else(|| invalid("missing shape"))?.0;
+    let shape = shape.split(',')
+        .map(|len| len.trim())
+        .filter(|len| !len.is_empty())
+        .map(|len| len.parse().map_err(|_| invalid("invalid shape")))
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    let data = &bytes[10 + header_len..];
+    if data.len() != 4 * shape.iter().product::<usize>() {
+        return Err(invalid("the size of the data does not match the shape"));
+    }
+    let data = data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+    Ok((shape, data))
+}
+
+/// Writes the fields as .vti file every few steps and a .pvd collection of these files with their times
+/// The collection is rewritten with every file, so it stays valid if the run is interrupted
+pub struct PvdSeries {
+    directory: PathBuf,
+    name: String,
+    every: u64,
+    fields: Vec<Field>,
+    datasets: Vec<(f32, String)>,
+}
+
+impl PvdSeries {
+    /// Writes name.pvd and name_000120.vti, ... into an existing directory
+    pub fn new(directory: impl Into<PathBuf>, name: &str, every: u64, fields: &[Field]) -> Self {
+        Self {
+            directory: directory.into(),
+            name: name.to_string(),
+            every: every.max(1),
+            fields: fields.to_vec(),
+            datasets: Vec::new(),
+        }
+    }
+
+    /// Writes a file if the number of steps is a multiple of every, returns if it did
+    pub fn record(&mut self, wave_equation: &WaveEquation) -> std::io::Result<bool> {
+        if !wave_equation.steps().is_multiple_of(self.every) {
+            return Ok(false);
+        }
+        self.write(wave_equation)?;
+        Ok(true)
+    }
+
+    /// Writes the current fields and adds them to the collection
+    pub fn write(&mut self, wave_equation: &WaveEquation) -> std::io::Result<()> {
+        let file = format!("{}_{:06}.vti", self.name, wave_equation.steps());
+        let writer = std::io::BufWriter::new(std::fs::File::create(self.directory.join(&file))?);
+        wave_equation.write_vti(&self.fields, writer)?;
+        self.datasets.push((wave_equation.time(), file));
+
+        let mut pvd = std::io::BufWriter::new(std::fs::File::create(self.directory.join(format!("{}.pvd", self.name)))?);
+        writeln!(pvd, "<?xml version=\"1.0\"?>")?;
+        writeln!(pvd, "<VTKFile type=\"Collection\" version=\"1.0\" byte_order=\"LittleEndian\">")?;
+        writeln!(pvd, "  <Collection>")?;
+        for (time, file) in &self.datasets {
+            writeln!(pvd, "    <DataSet timestep=\"{time}\" part=\"0\" file=\"{file}\"/>")?;
+        }
+        writeln!(pvd, "  </Collection>")?;
+        writeln!(pvd, "</VTKFile>")?;
+        pvd.flush()
+    }
+
+    /// Number of files written
+    pub fn datasets(&self) -> usize {
+        self.datasets.len()
+    }
+}
+
+// Writes a zip archive of uncompressed files, which is all .npz needs
+struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u32,
+    central_directory: Vec<u8>,
+    files: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offset: 0,
+            central_directory: Vec::new(),
+            files: 0,
+        }
+    }
+
+    fn add_file(&mut self, name: &str, data: &[u8]) -> std::io::Result<()> {
+        let crc = crc32(data);
+        // version 2.0, no flags, stored, modified at 1980-01-01 00:00
+        let mut common = Vec::new();
+        for value in [20u16, 0, 0, 0, 0x21] {
+            common.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [crc, data.len() as u32, data.len() as u32] {
+            common.extend_from_slice(&value.to_le_bytes());
+        }
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        let mut local_header = 0x0403_4b50u32.to_le_bytes().to_vec();
+        local_header.extend_from_slice(&common);
+        local_header.extend_from_slice(name.as_bytes());
+
+        // made by version 2.0, without comment, disk number and attributes
+        let entry = &mut self.central_directory;
+        entry.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        entry.extend_from_slice(&20u16.to_le_bytes());
+        entry.extend_from_slice(&common);
+        entry.extend_from_slice(&[0; 10]);
+        entry.extend_from_slice(&self.offset.to_le_bytes());
+        entry.extend_from_slice(name.as_bytes());
+
+        self.writer.write_all(&local_header)?;
+        self.writer.write_all(data)?;
+        self.offset += (local_header.len() + data.len()) as u32;
+        self.files += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.writer.write_all(&self.central_directory)?;
+        let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+        for value in [0u16, 0, self.files, self.files] {
+            end.extend_from_slice(&value.to_le_bytes());
+        }
+        end.extend_from_slice(&(self.central_directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&self.offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.writer.write_all(&end)?;
+        self.writer.flush()
+    }
+}
+
+// CRC-32 of zip files
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _bit| if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Obstacle;
+
+    const WIDTH: usize = 30;
+    const HEIGHT: usize = 20;
+
+    fn simulation() -> WaveEquation {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.obstacles_mut().stamp_rectangle(3.0, 4.0, 5.0, 6.0, Obstacle::Fixed);
+        wave_equation.add_impulse(10.0, 15.0);
+        wave_equation.interupt_mouse();
+        for _i in 0..5 {
+            wave_equation.step(None);
+        }
+        wave_equation
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let wave_equation = simulation();
+        for field in Field::ALL {
+            let mut file = Vec::new();
+            wave_equation.write_npy(field, &mut file).unwrap();
+            assert_eq!(file.len() % NPY_ALIGNMENT, (4 * WIDTH * HEIGHT) % NPY_ALIGNMENT);
+
+            let (shape, data) = read_npy(&file).unwrap();
+            assert_eq!(shape, [HEIGHT, WIDTH]);
+            assert_eq!(data, wave_equation.field(field));
+        }
+        let obstacles = wave_equation.field(Field::Obstacles);
+        assert_eq!(obstacles[4 * WIDTH + 3], 1.0);
+        assert_eq!(obstacles.iter().sum::<f32>(), 9.0);
+
+        let mut file = Vec::new();
+        write_npy(&mut file, &[1.0, -2.5, f32::MAX], &[3]).unwrap();
+        let header_len = u16::from_le_bytes([file[8], file[9]]) as usize;
+        assert_eq!((10 + header_len) % NPY_ALIGNMENT, 0);
+        let header = std::str::from_utf8(&file[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(read_npy(&file).unwrap(), (vec![3], vec![1.0, -2.5, f32::MAX]));
+
+        file.pop();
+        assert!(read_npy(&file).is_err());
+        assert!(read_npy(b"not a numpy file").is_err());
+    }
+
+    #[test]
+    fn npz_lists_one_array_per_field() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let wave_equation = simulation();
+        let mut file = Vec::new();
+        wave_equation.write_npz(&[Field::Displacement, Field::WaveSpeed], &mut file).unwrap();
+
+        // the first array follows its local header, the end of the archive counts both files
+        let name = b"displacement.npy";
+        assert_eq!(&file[..4], b"PK\x03\x04");
+        assert_eq!(&file[30..30 + name.len()], name);
+        let npy_len = u32::from_le_bytes(file[22..26].try_into().unwrap()) as usize;
+        let npy = &file[30 + name.len()..30 + name.len() + npy_len];
+        assert_eq!(read_npy(npy).unwrap().1, wave_equation.field(Field::Displacement));
+        assert_eq!(u32::from_le_bytes(file[14..18].try_into().unwrap()), crc32(npy));
+
+        let end = &file[file.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let central_directory = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(&file[central_directory..central_directory + 4], b"PK\x01\x02");
+        assert_eq!(&file[central_directory + 46..central_directory + 46 + name.len()], name);
+    }
+
+    #[test]
+    fn vti_has_one_value_per_node() {
+        let wave_equation = simulation();
+        let mut file = Vec::new();
+        wave_equation.write_vti(&[Field::Displacement, Field::Obstacles], &mut file).unwrap();
+        let text = String::from_utf8(file).unwrap();
+        assert!(text.contains("WholeExtent=\"0 29 0 19 0 0\""));
+        assert!(text.contains("Name=\"obstacles\""));
+
+        let values: Vec<f32> = text.split("format=\"ascii\">").nth(1).unwrap()
+            .split("</DataArray>").next().unwrap()
+            .split_whitespace().map(|value| value.parse().unwrap())
+            .collect();
+        assert_eq!(values, wave_equation.field(Field::Displacement));
+    }
+}
@@ -0,0 +1,208 @@
+//! Initial displacement and velocity of the grid
+//!
+
+use super::Integrator;
+use super::medium::grayscale_field;
+use super::WaveEquation;
+use super::Waveform;
+
+/// Preset initial states, positions and widths are given in nodes
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum InitialCondition {
+    /// Gaussian bump at rest with the standard deviation width, splits into an expanding ring
+    GaussianBump { x: f32, y: f32, width: f32, amplitude: f32 },
+    /// Ring shaped ridge at rest with a gaussian cross section
+    Ring { x: f32, y: f32, radius: f32, width: f32, amplitude: f32 },
+    /// Straight wave packet through x and y, travelling in the direction in rad (0 is along the x axis)
+    /// The profile is the waveform as it passes a fixed point, e.g. a Ricker wavelet with zero delay
+    PlaneWavePacket { x: f32, y: f32, direction: f32, waveform: Waveform, amplitude: f32 },
+}
+
+impl InitialCondition {
+    /// Displacement at the node x and y, for plane wave packets with the local wave speed c and the node distance h
+    pub fn displacement(&self, x: f32, y: f32, c: f32, h: f32) -> f32 {
+        match *self {
+            InitialCondition::GaussianBump { x: x_0, y: y_0, width, amplitude } => {
+                let r2 = (x - x_0).powi(2) + (y - y_0).powi(2);
+                amplitude * (-0.5 * r2 / (width * width)).exp()
+            },
+            InitialCondition::Ring { x: x_0, y: y_0, radius, width, amplitude } => {
+                let r = ((x - x_0).powi(2) + (y - y_0).powi(2)).sqrt();
+                amplitude * (-0.5 * ((r - radius) / width).powi(2)).exp()
+            },
+            InitialCondition::PlaneWavePacket { x: x_0, y: y_0, direction, waveform, amplitude } => {
+                // the node is passed by the packet after -s / c
+                let s = ((x - x_0) * direction.cos() + (y - y_0) * direction.sin()) * h;
+                amplitude * waveform.value(-s / c)
+            },
+        }
+    }
+
+    /// Velocity at the node x and y, only plane wave packets are moving
+    pub fn velocity(&self, x: f32, y: f32, c: f32, h: f32) -> f32 {
+        match *self {
+            InitialCondition::GaussianBump { .. } | InitialCondition::Ring { .. } => 0.0,
+            InitialCondition::PlaneWavePacket { x: x_0, y: y_0, direction, waveform, amplitude } => {
+                let s = ((x - x_0) * direction.cos() + (y - y_0) * direction.sin()) * h;
+                // central difference of the waveform
+                let epsilon = 1.0e-3;
+                amplitude * (waveform.value(-s / c + epsilon) - waveform.value(-s / c - epsilon)) / (2.0 * epsilon)
+            },
+        }
+    }
+}
+
+/// Converts a grayscale image to a displacement field, which is scaled to the grid
+/// Black maps to 0 and white to amplitude, the top row of the image is the top edge of the grid
+pub fn displacement_from_image(image: &image::DynamicImage, width: usize, height: usize, amplitude: f32) -> Vec<f32> {
+    grayscale_field(image, width, height).into_iter()
+        .map(|brightness| brightness * amplitude)
+        .collect()
+}
+
+impl WaveEquation {
+    /// Starts the simulation over from the given displacement and velocity of all nodes, stored row by row
+    /// Both time levels of the Verlet scheme are filled with a second order Taylor expansion,
+    /// nodes within obstacles stay at zero
+    pub fn set_initial_conditions(&mut self, displacement: &[f32], velocity: &[f32]) {
+        let len = self.width * self.height;
+        assert_eq!(displacement.len(), len, "displacement needs width * height values");
+        assert_eq!(velocity.len(), len, "velocity needs width * height values");
+
+        // the new state can not be replayed, so the recorded input ends here
+        self.close_recording();
+        self.reset_fields();
+        for ((u, displacement), cell) in self.current.iter_mut().zip(displacement).zip(self.obstacles.cells()) {
+            *u = if cell.is_none() { *displacement } else { 0.0 };
+        }
+
+        // u(-dt) = u - dt * v + dt^2 / 2 * c^2 * laplacian(u)
+        let delta_t = self.delta_t;
+        self.substep_delta_t = delta_t;
+        let mut laplacian = vec![0.0; len];
+        self.laplacian_field(&self.current, &mut laplacian, &self.per_node_rows());
+        let h2 = self.h * self.h;
+        for i in 0..len {
+            if self.obstacles.cells()[i].is_some() {
+                continue;
+            }
+            let c = self.medium.wave_speed()[i];
+            let acceleration = c * c * laplacian[i] / h2;
+            self.previous[i] = self.current[i] - delta_t * velocity[i] + 0.5 * delta_t * delta_t * acceleration;
+            // the leapfrog velocity lives half a time step behind
+            self.velocity[i] = match self.integrator {
+                Integrator::Leapfrog => (self.current[i] - self.previous[i]) / delta_t,
+                _ => velocity[i],
+            };
+        }
+    }
+
+    /// Starts the simulation over from the given displacement at rest
+    pub fn set_initial_displacement(&mut self, displacement: &[f32]) {
+        let velocity = vec![0.0; self.width * self.height];
+        self.set_initial_conditions(displacement, &velocity);
+    }
+
+    /// Starts the simulation over from one of the presets
+    pub fn set_initial_condition(&mut self, condition: InitialCondition) {
+        let len = self.width * self.height;
+        let mut displacement = vec![0.0; len];
+        let mut velocity = vec![0.0; len];
+        for i in 0..len {
+            let x = (i % self.width) as f32;
+            let y = (i / self.width) as f32;
+            let c = self.medium.wave_speed()[i];
+            displacement[i] = condition.displacement(x, y, c, self.h);
+            velocity[i] = condition.velocity(x, y, c, self.h);
+        }
+        self.set_initial_conditions(&displacement, &velocity);
+    }
+
+    /// Starts the simulation over from a grayscale image at rest, e.g. the content of a png file
+    pub fn set_initial_displacement_from_image_bytes(&mut self, bytes: &[u8], amplitude: f32) -> image::ImageResult<()> {
+        let image = image::load_from_memory(bytes)?;
+        let displacement = displacement_from_image(&image, self.width, self.height, amplitude);
+        self.set_initial_displacement(&displacement);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundaries;
+    use super::super::BoundaryCondition;
+    use super::super::Obstacle;
+
+    const WIDTH: usize = 120;
+    const HEIGHT: usize = 20;
+
+    #[test]
+    fn presets_have_expected_shape() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_initial_condition(InitialCondition::Ring { x: 60.0, y: 10.0, radius: 6.0, width: 1.0, amplitude: 0.5 });
+        let u = wave_equation.get_current();
+        assert_eq!(u[10 * WIDTH + 66], 0.5);
+        assert_eq!(u[4 * WIDTH + 60], 0.5);
+        assert!(u[10 * WIDTH + 60] < 1e-6);
+
+        wave_equation.obstacles_mut().stamp_circle(30.0, 10.0, 2.0, Obstacle::Fixed);
+        wave_equation.set_initial_condition(InitialCondition::GaussianBump { x: 30.0, y: 10.0, width: 4.0, amplitude: 1.0 });
+        let u = wave_equation.get_current();
+        assert_eq!(u[10 * WIDTH + 30], 0.0);
+        assert_eq!(u[10 * WIDTH + 34], (-0.5_f32).exp());
+        assert_eq!(wave_equation.steps(), 0);
+    }
+
+    #[test]
+    fn plane_wave_packet_travels_in_one_direction() {
+        for integrator in [Integrator::Verlet, Integrator::Leapfrog, Integrator::RungeKutta4] {
+            let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+            wave_equation.set_damping(0.0);
+            wave_equation.set_integrator(integrator);
+            let mut boundaries = Boundaries::uniform(BoundaryCondition::Periodic);
+            boundaries.left = BoundaryCondition::Absorbing;
+            boundaries.right = BoundaryCondition::Absorbing;
+            wave_equation.set_boundary_conditions(boundaries);
+
+            let waveform = Waveform::GaussianPulse { delay: 0.0, width: 0.5 };
+            wave_equation.set_initial_condition(InitialCondition::PlaneWavePacket { x: 40.0, y: 10.0, direction: 0.0, waveform, amplitude: 1.0 });
+
+            // 2 s move the packet by 2 / h = 16 nodes
+            for _i in 0..40 {
+                wave_equation.step(None);
+            }
+            let row = &wave_equation.get_current()[10 * WIDTH..11 * WIDTH];
+            let peak = (0..WIDTH).max_by(|a, b| row[*a].total_cmp(&row[*b])).unwrap();
+            assert!((peak as i32 - 56).abs() <= 1, "{integrator:?} peak at {peak}");
+            assert!((row[peak] - 1.0).abs() < 0.05, "{integrator:?} amplitude {}", row[peak]);
+            assert!(row[..40].iter().all(|u| u.abs() < 0.02), "{integrator:?} packet also travels backwards");
+        }
+    }
+
+    #[test]
+    fn displacement_from_png() {
+        // white square in the upper left corner of the image
+        let mut image = image::GrayImage::new(WIDTH as u32, HEIGHT as u32);
+        for y in 0..5 {
+            for x in 0..10 {
+                image.put_pixel(x, y, image::Luma([255]));
+            }
+        }
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageLuma8(image)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_initial_displacement_from_image_bytes(&bytes, 0.25).unwrap();
+        let u = wave_equation.get_current();
+        assert_eq!(u[(HEIGHT - 1) * WIDTH + 2], 0.25);
+        assert_eq!(u[2], 0.0);
+
+        assert!(wave_equation.set_initial_displacement_from_image_bytes(&[1, 2, 3], 1.0).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Time integration schemes of the wave equation
+//!
+//! All schemes solve u_tt + gamma * u_t = c^2 * laplacian(u) + f with the same spatial discretization.
+//! The schemes in velocity form store the displacement in current and the velocity in a separate field.
+//!
+
+use super::kernel;
+use super::Edge;
+use super::BoundaryCondition;
+use super::WaveEquation;
+
+/// Time integration scheme
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Default)]
+pub enum Integrator {
+    /// Three level scheme on the previous, current and next displacement, second order
+    #[default]
+    Verlet,
+    /// Displacement and velocity leapfrog, the velocity lives half a time step behind,
+    /// equivalent to the Verlet scheme but with the velocity at hand
+    Leapfrog,
+    /// Classic fourth order Runge Kutta on displacement and velocity, slightly dissipative
+    RungeKutta4,
+    /// Implicit trapezoidal rule, second order and unconditionally stable,
+    /// the linear system of every step is solved iteratively
+    CrankNicolson,
+}
+
+impl Integrator {
+    /// Factor of the stability limit relative to the Verlet scheme
+    pub fn stability_factor(&self) -> f32 {
+        match self {
+            Integrator::Verlet | Integrator::Leapfrog => 1.0,
+            // the stability region of RK4 reaches 2 * sqrt(2) on the imaginary axis, Verlet reaches 2
+            Integrator::RungeKutta4 => std::f32::consts::SQRT_2,
+            Integrator::CrankNicolson => f32::INFINITY,
+        }
+    }
+}
+
+// Stop criterion of the iterative solver, relative to the right hand side
+const SOLVER_TOLERANCE: f64 = 1.0e-6;
+const SOLVER_MAX_ITERATIONS: usize = 200;
+
+impl WaveEquation {
+    // leapfrog, v^(n+1/2) = ((1 - a) * v^(n-1/2) + dt * acceleration(u^n)) / (1 + a), u^(n+1) = u^n + dt * v^(n+1/2)
+    pub(super) fn step_leapfrog(&mut self, next: &mut [f32], forces: &[f32], per_node_rows: &[bool], delta_t: f32) {
+        let mut laplacian = vec![0.0; self.width * self.height];
+        self.laplacian_field(&self.current, &mut laplacian, per_node_rows);
+
+        let h2 = self.h * self.h;
+        let wave_speed = self.medium.wave_speed();
+        for i in 0..next.len() {
+            if self.obstacles.cells()[i].is_some() {
+                self.velocity[i] = 0.0;
+                next[i] = 0.0;
+                continue;
+            }
+            let a = 0.5 * (self.damping[i] + self.sponge[i]) * delta_t;
+            let c = wave_speed[i];
+            let acceleration = (c * c * laplacian[i] + forces[i]) / h2;
+            self.velocity[i] = ((1.0 - a) * self.velocity[i] + delta_t * acceleration) / (1.0 + a);
+            next[i] = self.current[i] + delta_t * self.velocity[i];
+        }
+    }
+
+    // classic Runge Kutta with the stages 0, dt/2, dt/2, dt and the weights 1/6, 2/6, 2/6, 1/6
+    pub(super) fn step_runge_kutta_4(&mut self, next: &mut [f32], forces: &[f32], per_node_rows: &[bool], delta_t: f32) {
+        let len = self.width * self.height;
+        let mut laplacian = vec![0.0; len];
+        let mut stage_u = self.current.clone();
+        let mut stage_v = self.velocity.clone();
+        let mut next_v = self.velocity.clone();
+        next.copy_from_slice(&self.current);
+
+        let h2 = self.h * self.h;
+        let wave_speed = self.medium.wave_speed();
+        let stages = [(1.0 / 6.0, 0.5), (2.0 / 6.0, 0.5), (2.0 / 6.0, 1.0), (1.0 / 6.0, 0.0)];
+        for (weight, next_stage) in stages {
+            self.laplacian_field(&stage_u, &mut laplacian, per_node_rows);
+            for i in 0..len {
+                if self.obstacles.cells()[i].is_some() {
+                    continue;
+                }
+                let c = wave_speed[i];
+                let k_u = stage_v[i];
+                let k_v = (c * c * laplacian[i] + forces[i]) / h2 - (self.damping[i] + self.sponge[i]) * stage_v[i];
+                next[i] += delta_t * weight * k_u;
+                next_v[i] += delta_t * weight * k_v;
+                stage_u[i] = self.current[i] + delta_t * next_stage * k_u;
+                stage_v[i] = self.velocity[i] + delta_t * next_stage * k_v;
+            }
+        }
+
+        for (i, cell) in self.obstacles.cells().iter().enumerate() {
+            if cell.is_some() {
+                next[i] = 0.0;
+                next_v[i] = 0.0;
+            }
+        }
+        self.velocity = next_v;
+    }
+
+    // trapezoidal rule on u_t = v and v_t = A u + f - gamma * v with A = c^2 * laplacian / h^2,
+    // eliminating v^(n+1) gives with b = dt/2
+    // ((1 + b * gamma) - b^2 * A) u^(n+1) = (1 + b * gamma) u^n + 2 * b * v^n + b^2 * A u^n + b * dt * f
+    pub(super) fn step_crank_nicolson(&mut self, next: &mut [f32], forces: &[f32], per_node_rows: &[bool], delta_t: f32) {
+        let len = self.width * self.height;
+        let b = 0.5 * delta_t;
+        let h2 = self.h * self.h;
+        let diagonal: Vec<f32> = self.damping.iter().zip(&self.sponge).map(|(damping, sponge)| 1.0 + b * (damping + sponge)).collect();
+        let coupling: Vec<f32> = self.medium.wave_speed().iter().map(|c| b * b * c * c / h2).collect();
+
+        let mut rhs = vec![0.0; len];
+        self.laplacian_field(&self.current, &mut rhs, per_node_rows);
+        for i in 0..len {
+            rhs[i] = if self.obstacles.cells()[i].is_some() {
+                0.0
+            }
+            else {
+                diagonal[i] * self.current[i] + 2.0 * b * self.velocity[i] + coupling[i] * rhs[i] + b * delta_t * forces[i] / h2
+            };
+        }
+
+        // the system matrix, obstacle nodes are decoupled with u = 0
+        let apply = |u: &[f32], result: &mut [f32]| {
+            self.laplacian_field(u, result, per_node_rows);
+            for i in 0..len {
+                result[i] = if self.obstacles.cells()[i].is_some() {
+                    u[i]
+                }
+                else {
+                    diagonal[i] * u[i] - coupling[i] * result[i]
+                };
+            }
+        };
+
+        // the explicit prediction is a good starting point
+        for ((next, u), v) in next.iter_mut().zip(&self.current).zip(&self.velocity) {
+            *next = u + delta_t * v;
+        }
+        bicgstab(apply, &rhs, next);
+
+        let nodes = next.iter_mut().zip(&mut self.velocity).zip(&self.current).zip(self.obstacles.cells());
+        for (((next, v), u), cell) in nodes {
+            if cell.is_some() {
+                *next = 0.0;
+                *v = 0.0;
+            }
+            else {
+                *v = (*next - u) / b - *v;
+            }
+        }
+    }
+
+    // The boundary conditions overwrite the displacement of the edges, the velocity follows from the difference
+    pub(super) fn update_edge_velocity(&mut self, next: &[f32], delta_t: f32) {
+        let width = self.width;
+        let height = self.height;
+        for edge in [Edge::Left, Edge::Right, Edge::Bottom, Edge::Top] {
+            if !matches!(self.boundaries.get(edge), BoundaryCondition::Fixed | BoundaryCondition::Absorbing) {
+                continue;
+            }
+            let nodes: Vec<usize> = match edge {
+                Edge::Left => (0..height).map(|y| y * width).collect(),
+                Edge::Right => (0..height).map(|y| y * width + width - 1).collect(),
+                Edge::Bottom => (0..width).collect(),
+                Edge::Top => (0..width).map(|x| (height - 1) * width + x).collect(),
+            };
+            for i in nodes {
+                self.velocity[i] = (next[i] - self.current[i]) / delta_t;
+            }
+        }
+    }
+
+    // Stencil of the field u for all nodes, without the factor 1/h^2
+    pub(super) fn laplacian_field(&self, u: &[f32], laplacian: &mut [f32], per_node_rows: &[bool]) {
+        let width = self.width;
+        let radius = self.stencil.radius();
+        for (y, row) in laplacian.chunks_mut(width).enumerate() {
+            if per_node_rows[y] {
+                for (x, laplacian) in row.iter_mut().enumerate() {
+                    *laplacian = self.laplacian(u, x, y);
+                }
+            }
+            else {
+                for x in (0..radius).chain(width - radius..width) {
+                    row[x] = self.laplacian(u, x, y);
+                }
+                kernel::interior_laplacian(self.stencil, u, width, y, radius, &mut row[radius..width - radius]);
+            }
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| *a as f64 * *b as f64).sum()
+}
+
+// Solves the linear system apply(x) = rhs with the biconjugate gradient stabilized method,
+// the system is not symmetric for a varying medium, x holds the initial guess
+fn bicgstab(apply: impl Fn(&[f32], &mut [f32]), rhs: &[f32], x: &mut [f32]) {
+    let len = rhs.len();
+    let tolerance = SOLVER_TOLERANCE * dot(rhs, rhs).sqrt();
+
+    let mut r = vec![0.0; len];
+    apply(x, &mut r);
+    for (r, rhs) in r.iter_mut().zip(rhs) {
+        *r = rhs - *r;
+    }
+    let r_0 = r.clone();
+    let mut p = vec![0.0; len];
+    let mut v = vec![0.0; len];
+    let mut s = vec![0.0; len];
+    let mut t = vec![0.0; len];
+    let (mut rho, mut alpha, mut omega) = (1.0, 1.0, 1.0);
+
+    for _i in 0..SOLVER_MAX_ITERATIONS {
+        if dot(&r, &r).sqrt() <= tolerance {
+            return;
+        }
+        let rho_new = dot(&r_0, &r);
+        let beta = (rho_new / rho) * (alpha / omega);
+        rho = rho_new;
+        for i in 0..len {
+            p[i] = r[i] + (beta * (p[i] as f64 - omega * v[i] as f64)) as f32;
+        }
+        apply(&p, &mut v);
+        alpha = rho / dot(&r_0, &v);
+        for i in 0..len {
+            s[i] = r[i] - (alpha * v[i] as f64) as f32;
+        }
+        if dot(&s, &s).sqrt() <= tolerance {
+            for i in 0..len {
+                x[i] += (alpha * p[i] as f64) as f32;
+            }
+            return;
+        }
+        apply(&s, &mut t);
+        omega = dot(&t, &s) / dot(&t, &t);
+        for i in 0..len {
+            x[i] += (alpha * p[i] as f64 + omega * s[i] as f64) as f32;
+            r[i] = s[i] - (omega * t[i] as f64) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundaries;
+    use super::super::Stencil;
+    use super::super::WaveEquationConfig;
+
+    const SIZE: usize = 32;
+    // periods of the standing wave across the grid
+    const M_X: usize = 4;
+    const M_Y: usize = 2;
+
+    // Standing wave cos(theta_x * x) * cos(theta_y * y) * cos(omega * t), an eigenmode of the discrete laplacian,
+    // omega is the angular frequency of the exact solution in time of the spatially discretized equation
+    struct StandingWave {
+        theta_x: f32,
+        theta_y: f32,
+        omega: f32,
+    }
+
+    impl StandingWave {
+        fn new(stencil: Stencil, h: f32) -> Self {
+            let theta_x = 2.0 * std::f32::consts::PI * M_X as f32 / SIZE as f32;
+            let theta_y = 2.0 * std::f32::consts::PI * M_Y as f32 / SIZE as f32;
+            let eigenvalue: f32 = stencil.center() + stencil.neighbours().iter()
+                .map(|(d_x, d_y, weight)| weight * (theta_x * *d_x as f32).cos() * (theta_y * *d_y as f32).cos())
+                .sum::<f32>();
+
+            Self { theta_x, theta_y, omega: (-eigenvalue).sqrt() / h }
+        }
+
+        fn displacement(&self, x: usize, y: usize, t: f32) -> f32 {
+            (self.theta_x * x as f32).cos() * (self.theta_y * y as f32).cos() * (self.omega * t).cos()
+        }
+
+        fn velocity(&self, x: usize, y: usize, t: f32) -> f32 {
+            -self.omega * (self.theta_x * x as f32).cos() * (self.theta_y * y as f32).cos() * (self.omega * t).sin()
+        }
+    }
+
+    // Largest deviation from the standing wave during 1 second
+    fn error(integrator: Integrator, delta_t: f32) -> f32 {
+        let mut config = WaveEquationConfig::new(SIZE, SIZE);
+        config.delta_t = delta_t;
+        config.damping = 0.0;
+        config.integrator = integrator;
+        let mut wave_equation = WaveEquation::with_config(config).unwrap();
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Periodic));
+        let wave = StandingWave::new(wave_equation.stencil(), wave_equation.h);
+
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let i = y * SIZE + x;
+                wave_equation.current[i] = wave.displacement(x, y, 0.0);
+                wave_equation.previous[i] = wave.displacement(x, y, -delta_t);
+                wave_equation.velocity[i] = match integrator {
+                    Integrator::Leapfrog => (wave.displacement(x, y, 0.0) - wave.displacement(x, y, -delta_t)) / delta_t,
+                    _ => wave.velocity(x, y, 0.0),
+                };
+            }
+        }
+
+        let mut error: f32 = 0.0;
+        let steps = (1.0 / delta_t).round() as usize;
+        for step in 1..=steps {
+            wave_equation.step(None);
+            let t = step as f32 * delta_t;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    error = error.max((wave_equation.get_current()[y * SIZE + x] - wave.displacement(x, y, t)).abs());
+                }
+            }
+        }
+        error
+    }
+
+    #[test]
+    fn integrators_converge_to_standing_wave() {
+        // the error of RK4 reaches the precision of f32 quickly, so it is tested with larger time steps
+        for (integrator, order, delta_t) in [
+            (Integrator::Verlet, 2, 0.05),
+            (Integrator::Leapfrog, 2, 0.05),
+            (Integrator::RungeKutta4, 4, 0.1),
+            (Integrator::CrankNicolson, 2, 0.05)] {
+            let coarse = error(integrator, delta_t);
+            let fine = error(integrator, 0.5 * delta_t);
+            let rate = (coarse / fine).log2();
+            assert!(coarse < 0.1, "{integrator:?} error {coarse}");
+            assert!((rate - order as f32).abs() < 0.3, "{integrator:?} convergence rate {rate}, errors {coarse} {fine}");
+        }
+    }
+
+    // Quadratic energy 0.5 * v^2 - 0.5 * c^2 / h^2 * u * laplacian(u), which the trapezoidal rule conserves without damping
+    fn energy(wave_equation: &WaveEquation) -> f32 {
+        let mut laplacian = vec![0.0; SIZE * SIZE];
+        wave_equation.laplacian_field(wave_equation.get_current(), &mut laplacian, &wave_equation.per_node_rows());
+        let h2 = wave_equation.h * wave_equation.h;
+        let nodes = wave_equation.velocity.iter().zip(&wave_equation.current).zip(&laplacian).zip(wave_equation.medium.wave_speed());
+        nodes.map(|(((v, u), laplacian), c)| 0.5 * v * v - 0.5 * c * c / h2 * u * laplacian).sum()
+    }
+
+    #[test]
+    fn crank_nicolson_allows_large_time_steps() {
+        let mut config = WaveEquationConfig::new(SIZE, SIZE);
+        config.delta_t = 0.5; // courant number 4
+        assert!(WaveEquation::with_config(config).is_err());
+
+        config.integrator = Integrator::CrankNicolson;
+        let mut wave_equation = WaveEquation::with_config(config).unwrap();
+        wave_equation.add_impulse(16.3, 16.6);
+        wave_equation.step(None);
+        let initial = energy(&wave_equation);
+        for _i in 0..100 {
+            wave_equation.step(None);
+            assert!(energy(&wave_equation) <= initial * 1.001);
+        }
+        assert_eq!(wave_equation.divergence(), None);
+    }
+
+    #[test]
+    fn leapfrog_matches_verlet() {
+        let mut verlet = WaveEquation::new(SIZE, SIZE);
+        let mut leapfrog = WaveEquation::new(SIZE, SIZE);
+        leapfrog.set_integrator(Integrator::Leapfrog);
+        for i in 0..50 {
+            verlet.add_impulse(10.3 + 0.2 * i as f32, 12.6);
+            leapfrog.add_impulse(10.3 + 0.2 * i as f32, 12.6);
+            verlet.step(None);
+            leapfrog.step(None);
+        }
+        for (a, b) in verlet.get_current().iter().zip(leapfrog.get_current()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!(leapfrog.get_velocity().is_some());
+        assert!(verlet.get_velocity().is_none());
+    }
+}
@@ -0,0 +1,43 @@
+//! Inner loops of the time step
+//!
+
+use super::Stencil;
+
+/// Implementation of the time step, both give bit identical results
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Default)]
+pub enum Kernel {
+    /// Resolves the boundary conditions and obstacles for every neighbour of every node
+    PerNode,
+    /// Processes the interior of the grid row by row on contiguous slices, which the compiler vectorizes,
+    /// only the edges and the rows next to obstacles go through the per node path
+    #[default]
+    Interior,
+}
+
+/// Verlet update of u_tt + gamma * u_t = c^2 * laplacian(u) + f, discretized with central differences
+/// a is 0.5 * gamma * delta_t and delta_t_h2 is delta_t^2 / h^2
+#[inline(always)]
+pub(super) fn verlet(current: f32, previous: f32, laplacian: f32, wave_speed: f32, force: f32, a: f32, delta_t_h2: f32) -> f32 {
+    (2.0 * current - (1.0 - a) * previous + delta_t_h2 * (wave_speed * wave_speed * laplacian + force)) / (1.0 + a)
+}
+
+/// Writes the laplacian without the factor 1/h^2 of the nodes x_0..x_0 + laplacian.len() in row y
+/// All neighbours have to lie within the grid, the sums are formed in the same order as in the per node path
+pub(super) fn interior_laplacian(stencil: Stencil, current: &[f32], width: usize, y: usize, x_0: usize, laplacian: &mut [f32]) {
+    let len = laplacian.len();
+    laplacian.fill(0.0);
+    for &(d_x, d_y, weight) in stencil.neighbours() {
+        let start = (y as isize + d_y) as usize * width + (x_0 as isize + d_x) as usize;
+        for (laplacian, u) in laplacian.iter_mut().zip(&current[start..start + len]) {
+            *laplacian += weight * u;
+        }
+    }
+    let center = stencil.center();
+    let start = y * width + x_0;
+    for (laplacian, u) in laplacian.iter_mut().zip(&current[start..start + len]) {
+        *laplacian += center * u;
+    }
+}
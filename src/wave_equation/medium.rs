@@ -0,0 +1,163 @@
+//! The medium the waves travel through, stores the wave speed of every node
+//!
+//! Waves are refracted where the wave speed changes,
+//! e.g. a circle of low wave speed acts as a lens and a slow stripe as a waveguide
+//!
+
+use super::Shape;
+
+#[derive(Clone)]
+pub struct Medium {
+    width: usize,
+    height: usize,
+
+    wave_speed: Vec<f32>,
+}
+
+// Brightness of a grayscale image between 0 and 1 for every node, the image is scaled to the grid
+// The rows are flipped, so that the top row of the image is the top edge of the grid
+pub(super) fn grayscale_field(image: &image::DynamicImage, width: usize, height: usize) -> Vec<f32> {
+    let gray = image.to_luma8();
+    let gray = image::imageops::resize(&gray, width as u32, height as u32, image::imageops::FilterType::Triangle);
+
+    let mut field = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            field[y * width + x] = gray.get_pixel(x as u32, (height - 1 - y) as u32).0[0] as f32 / 255.0;
+        }
+    }
+
+    field
+}
+
+impl Medium {
+    /// Creates a homogeneous medium
+    pub fn new(width: usize, height: usize, wave_speed: f32) -> Self {
+        Self {
+            width,
+            height,
+            wave_speed: vec![wave_speed; width * height],
+        }
+    }
+
+    /// Creates a medium from a grayscale image, which is scaled to the grid
+    /// Black maps to min_speed and white to max_speed, the top row of the image is the top edge of the grid
+    pub fn from_image(image: &image::DynamicImage, width: usize, height: usize, min_speed: f32, max_speed: f32) -> Self {
+        Self {
+            width,
+            height,
+            wave_speed: grayscale_field(image, width, height).into_iter()
+                .map(|brightness| min_speed + brightness * (max_speed - min_speed))
+                .collect(),
+        }
+    }
+
+    /// Creates a medium from an encoded image, e.g. the content of a png file
+    pub fn from_image_bytes(bytes: &[u8], width: usize, height: usize, min_speed: f32, max_speed: f32) -> image::ImageResult<Self> {
+        let image = image::load_from_memory(bytes)?;
+
+        Ok(Self::from_image(&image, width, height, min_speed, max_speed))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.wave_speed[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, wave_speed: f32) {
+        self.wave_speed[y * self.width + x] = wave_speed;
+    }
+
+    /// Sets the wave speed relative to a reference speed of 1, c = 1 / n
+    pub fn set_refractive_index(&mut self, x: usize, y: usize, refractive_index: f32) {
+        self.set(x, y, 1.0 / refractive_index);
+    }
+
+    /// Sets the wave speed of all nodes within the shape
+    pub fn fill(&mut self, shape: Shape, wave_speed: f32) {
+        let width = self.width;
+        shape.for_each_node(self.width, self.height, |x, y| self.wave_speed[y * width + x] = wave_speed);
+    }
+
+    /// Returns the wave speed of all nodes, stored row by row
+    pub fn wave_speed(&self) -> &[f32] {
+        &self.wave_speed
+    }
+
+    /// The largest wave speed, which limits the time step
+    pub fn max_wave_speed(&self) -> f32 {
+        self.wave_speed.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::WaveEquation;
+
+    const WIDTH: usize = 80;
+    const HEIGHT: usize = 10;
+
+    // Steps a gaussian ridge at rest in the middle of the grid and returns the position of the right peak
+    fn peak_position(wave_speed: f32, steps: usize) -> usize {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_medium(Medium::new(WIDTH, HEIGHT, wave_speed));
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let val = (-(x as f32 - 20.0).powi(2) / 8.0).exp();
+                wave_equation.current[y * WIDTH + x] = val;
+                wave_equation.previous[y * WIDTH + x] = val;
+            }
+        }
+
+        for _i in 0..steps {
+            wave_equation.step(None);
+        }
+
+        let row = &wave_equation.get_current()[5 * WIDTH..6 * WIDTH];
+        (20..WIDTH).max_by(|a, b| row[*a].total_cmp(&row[*b])).unwrap()
+    }
+
+    #[test]
+    fn slow_medium_slows_down_waves() {
+        // 0.4 nodes per step with c = 1, starting at 20
+        assert!(peak_position(1.0, 50).abs_diff(40) <= 1);
+        assert!(peak_position(0.5, 50).abs_diff(30) <= 1);
+    }
+
+    #[test]
+    fn courant_number_uses_maximum_wave_speed() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let courant = wave_equation.courant_number();
+        assert!(wave_equation.is_stable());
+
+        let mut medium = Medium::new(WIDTH, HEIGHT, 1.0);
+        medium.fill(Shape::Circle { x: 40.0, y: 5.0, radius: 2.0 }, 2.0);
+        wave_equation.set_medium(medium);
+        assert_eq!(wave_equation.courant_number(), 2.0 * courant);
+        assert!(!wave_equation.is_stable());
+    }
+
+    #[test]
+    fn image_is_mapped_to_wave_speed() {
+        // white top row, black bottom row
+        let mut image = image::GrayImage::new(4, 2);
+        for x in 0..4 {
+            image.put_pixel(x, 0, image::Luma([255]));
+        }
+        let medium = Medium::from_image(&image::DynamicImage::ImageLuma8(image), 4, 2, 0.5, 1.5);
+
+        for x in 0..4 {
+            assert_eq!(medium.get(x, 0), 0.5);
+            assert_eq!(medium.get(x, 1), 1.5);
+        }
+        assert_eq!(medium.max_wave_speed(), 1.5);
+    }
+}
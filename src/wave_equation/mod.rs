@@ -1,87 +1,674 @@
 //! Implementation of a 2D wave equation
 //!
-//! It uses the Verlet Method on a 2D grid 
+//! It is solved on a 2D grid with the Verlet method by default, Leapfrog, Runge-Kutta 4 and Crank-Nicolson can be selected as integrator
 //!
 
-use cgmath::num_traits::Pow;
+mod boundary_condition;
+mod colormap;
+mod config;
+mod diagnostics;
+mod error;
+mod export;
+mod initial_condition;
+mod integrator;
+mod kernel;
+mod medium;
+mod obstacle;
+mod preset;
+mod probe;
+mod replay;
+mod scenario;
+mod shape;
+mod snapshot;
+mod source;
+mod stencil;
+mod video;
+mod waveform;
 
-pub struct WaveEquation<const M:usize, const N:usize> {
+pub use boundary_condition::BoundaryCondition;
+pub use boundary_condition::Boundaries;
+pub use boundary_condition::Edge;
+pub use boundary_condition::SpongeLayer;
+pub use colormap::ColorRange;
+pub use colormap::Colormap;
+pub use colormap::PngSequence;
+pub use config::WaveEquationConfig;
+pub use diagnostics::Diagnostics;
+pub use diagnostics::DiagnosticsHistory;
+pub use error::Divergence;
+pub use error::FrameError;
+pub use error::ScenarioError;
+pub use error::SnapshotError;
+pub use error::WaveEquationError;
+pub use export::Field;
+pub use export::PvdSeries;
+pub use export::read_npy;
+pub use export::write_npy;
+pub use initial_condition::InitialCondition;
+pub use initial_condition::displacement_from_image;
+pub use integrator::Integrator;
+pub use kernel::Kernel;
+pub use medium::Medium;
+pub use obstacle::Obstacle;
+pub use obstacle::ObstacleMask;
+pub use preset::Preset;
+pub use probe::Probe;
+pub use probe::ProbeSample;
+pub use probe::write_probes_csv;
+pub use replay::InputEvent;
+pub use replay::InputRecording;
+pub use replay::RecordedInput;
+pub use replay::REPLAY_VERSION;
+pub use replay::Replay;
+pub use scenario::Scenario;
+pub use shape::Shape;
+pub use snapshot::SNAPSHOT_VERSION;
+pub use source::Envelope;
+pub use source::MovingSource;
+pub use source::PlaneWaveSource;
+pub use source::PointSource;
+pub use source::SourceId;
+pub use source::SourcePath;
+pub use stencil::Stencil;
+pub use video::CropRegion;
+pub use video::FrameOptions;
+pub use video::GifWriter;
+pub use video::Y4mWriter;
+pub use waveform::Waveform;
 
-    previous: Box<[[f32; N]; M]>,
-    current: Box<[[f32; N]; M]>,
-    next: Box<[[f32; N]; M]>,
-    forces: Box<[[f32; N]; M]>,
+// All available cores with the feature parallel, otherwise the calling thread only
+fn default_threads() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))] {
+            std::thread::available_parallelism().map_or(1, |threads| threads.get())
+        }
+        else {
+            1
+        }
+    }
+}
+
+pub struct WaveEquation {
+    width: usize,
+    height: usize,
+
+    previous: Vec<f32>,
+    current: Vec<f32>,
+    next: Vec<f32>,
+    forces: Vec<f32>,
     h: f32,
     delta_t: f32,
+    /// time step of the last substep, previous lies this far behind current
+    substep_delta_t: f32,
+    stencil: Stencil,
+    kernel: Kernel,
+    integrator: Integrator,
+    /// velocity of the nodes, only used by the integrators in velocity form
+    velocity: Vec<f32>,
+    damping: Vec<f32>,
+    medium: Medium,
+    obstacles: ObstacleMask,
+    boundaries: Boundaries,
+    sponge_layer: SpongeLayer,
+    sponge: Vec<f32>,
+    max_amplitude: f32,
+
+    threads: usize,
+
+    sources: Vec<(SourceId, PointSource)>,
+    plane_waves: Vec<(SourceId, PlaneWaveSource)>,
+    moving_sources: Vec<(SourceId, MovingSource)>,
+    next_source_id: u64,
+
+    steps: u64,
+    divergence: Option<Divergence>,
 
     x_old: f32,
     y_old: f32,
     mouse_interupted: bool,
+
+    recording: Option<InputRecording>,
 }
 
-impl<const M:usize, const N:usize>  WaveEquation<M, N>{
-
-    pub fn new() -> Self {
-        let previous: Box<[[f32; N]; M]> = unsafe {Box::<[[f32; N]; M]>::new_zeroed().assume_init()};
-        let current: Box<[[f32; N]; M]> = unsafe {Box::<[[f32; N]; M]>::new_zeroed().assume_init()};
-        let next: Box<[[f32; N]; M]> = unsafe {Box::<[[f32; N]; M]>::new_zeroed().assume_init()};   
-        let forces: Box<[[f32; N]; M]> = unsafe {Box::<[[f32; N]; M]>::new_zeroed().assume_init()};   
-        const H: f32 = 0.125;
-        const DELTA_T: f32 = 0.05;
-
-        Self {
-            previous,
-            current,
-            next,
-            forces,
-            h: H,
-            delta_t: DELTA_T,
+impl WaveEquation {
+
+    /// Creates a flat wave grid with width * height nodes and the default parameters
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::with_config(WaveEquationConfig::new(width, height)).expect("invalid grid size")
+    }
+
+    /// Creates a flat wave grid, fails if the parameters are invalid or violate the stability condition
+    pub fn with_config(config: WaveEquationConfig) -> Result<Self, WaveEquationError> {
+        let WaveEquationConfig { width, height, .. } = config;
+        if width < 3 || height < 3 {
+            return Err(WaveEquationError::InvalidGridSize { width, height });
+        }
+        for (name, value) in [
+            ("h", config.h), 
+            ("delta_t", config.delta_t), 
+            ("wave_speed", config.wave_speed), 
+            ("max_amplitude", config.max_amplitude)] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(WaveEquationError::InvalidParameter { name, value });
+            }
+        }
+        if !config.damping.is_finite() || config.damping < 0.0 {
+            return Err(WaveEquationError::InvalidParameter { name: "damping", value: config.damping });
+        }
+
+        let wave_equation = Self {
+            width,
+            height,
+            previous: vec![0.0; width * height],
+            current: vec![0.0; width * height],
+            next: vec![0.0; width * height],
+            forces: vec![0.0; width * height],
+            h: config.h,
+            delta_t: config.delta_t,
+            substep_delta_t: config.delta_t,
+            stencil: config.stencil,
+            kernel: Kernel::default(),
+            integrator: config.integrator,
+            velocity: vec![0.0; width * height],
+            damping: vec![config.damping; width * height],
+            medium: Medium::new(width, height, config.wave_speed),
+            obstacles: ObstacleMask::new(width, height),
+            boundaries: Boundaries::default(),
+            sponge_layer: SpongeLayer::default(),
+            sponge: vec![0.0; width * height],
+            max_amplitude: config.max_amplitude,
+            threads: default_threads(),
+            sources: Vec::new(),
+            plane_waves: Vec::new(),
+            moving_sources: Vec::new(),
+            next_source_id: 0,
+            steps: 0,
+            divergence: None,
             x_old: 0.,
             y_old: 0.,
             mouse_interupted: true,
+            recording: None,
+        };
+        wave_equation.check_stability()?;
+
+        Ok(wave_equation)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stencil(&self) -> Stencil {
+        self.stencil
+    }
+
+    /// Selects the discretization of the laplacian, higher order stencils reduce the grid anisotropy
+    /// but have a lower stability limit, see check_stability
+    pub fn set_stencil(&mut self, stencil: Stencil) {
+        self.stencil = stencil;
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    /// Switches the time integration scheme and converts the state,
+    /// the velocity is estimated from the last two time levels when leaving the Verlet scheme
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        if self.integrator == Integrator::Verlet && integrator != Integrator::Verlet {
+            for ((v, u), u_old) in self.velocity.iter_mut().zip(&self.current).zip(&self.previous) {
+                *v = (u - u_old) / self.substep_delta_t;
+            }
+        }
+        // the previous time level is kept up to date by all integrators
+        self.integrator = integrator;
+    }
+
+    /// Returns the velocity of all nodes, stored row by row
+    /// Only available with the integrators in velocity form, Leapfrog stores it half a time step behind the displacement
+    pub fn get_velocity(&self) -> Option<&[f32]> {
+        match self.integrator {
+            Integrator::Verlet => None,
+            _ => Some(&self.velocity),
         }
     }
 
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    /// Selects the implementation of the time step, mainly to compare their performance
+    pub fn set_kernel(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Number of threads sharing the rows of the grid in step, 1 steps on the calling thread
+    /// Only has an effect with the feature parallel on native targets, the results are identical for any number
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Returns the damping coefficient of all nodes in 1/s, stored row by row
+    pub fn damping(&self) -> &[f32] {
+        &self.damping
+    }
+
+    /// Sets the damping coefficient gamma of u_tt + gamma * u_t = c^2 * laplacian(u) for all nodes in 1/s
+    /// The amplitude of a wave decays by exp(-gamma * t / 2), independent of the time step
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping.fill(damping);
+    }
+
+    /// Sets a spatially varying damping coefficient in 1/s, stored row by row
+    pub fn set_damping_field(&mut self, damping: Vec<f32>) {
+        assert_eq!(damping.len(), self.width * self.height, "damping field does not match the grid size");
+        self.damping = damping;
+    }
+
+    pub fn medium(&self) -> &Medium {
+        &self.medium
+    }
+
+    pub fn medium_mut(&mut self) -> &mut Medium {
+        &mut self.medium
+    }
+
+    /// Replaces the wave speed of the whole grid, the medium needs to have the size of the grid
+    pub fn set_medium(&mut self, medium: Medium) {
+        assert_eq!((medium.width(), medium.height()), (self.width, self.height), "medium does not match the grid size");
+        self.medium = medium;
+    }
+
+    pub fn obstacles(&self) -> &ObstacleMask {
+        &self.obstacles
+    }
+
+    pub fn obstacles_mut(&mut self) -> &mut ObstacleMask {
+        &mut self.obstacles
+    }
+
+    /// Replaces all obstacles, the mask needs to have the size of the grid
+    pub fn set_obstacles(&mut self, obstacles: ObstacleMask) {
+        assert_eq!((obstacles.width(), obstacles.height()), (self.width, self.height), "obstacle mask does not match the grid size");
+        self.obstacles = obstacles;
+    }
+
+    /// c * delta_t / h for the largest wave speed in the medium
+    pub fn courant_number(&self) -> f32 {
+        self.medium.max_wave_speed() * self.delta_t / self.h
+    }
+
+    /// The largest stable courant number, 1/sqrt(2) for the 5 point stencil with the Verlet scheme in 2D
+    pub fn stability_limit(&self) -> f32 {
+        self.stencil.stability_limit() * self.integrator.stability_factor()
+    }
+
+    pub fn is_stable(&self) -> bool {
+        self.courant_number() <= self.stability_limit()
+    }
+
+    /// Checks the courant condition for the largest wave speed, e.g. after the medium was changed
+    pub fn check_stability(&self) -> Result<(), WaveEquationError> {
+        if self.is_stable() {
+            Ok(())
+        }
+        else {
+            Err(WaveEquationError::Unstable { courant_number: self.courant_number(), limit: self.stability_limit() })
+        }
+    }
+
+    /// Number of calls to step since the start
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Simulated time in s since the start or the last reset
+    pub fn time(&self) -> f32 {
+        self.steps as f32 * self.delta_t
+    }
+
+    /// Adds a source which injects its force in every step, until it is removed
+    pub fn add_source(&mut self, source: PointSource) -> SourceId {
+        let id = SourceId(self.next_source_id);
+        self.next_source_id += 1;
+        self.sources.push((id, source));
+        id
+    }
+
+    /// Iterates over all sources in the order they were added
+    pub fn sources(&self) -> impl Iterator<Item = (SourceId, &PointSource)> {
+        self.sources.iter().map(|(id, source)| (*id, source))
+    }
+
+    pub fn source(&self, id: SourceId) -> Option<&PointSource> {
+        self.sources.iter().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn source_mut(&mut self, id: SourceId) -> Option<&mut PointSource> {
+        self.sources.iter_mut().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn remove_source(&mut self, id: SourceId) -> Option<PointSource> {
+        let index = self.sources.iter().position(|(source_id, _)| *source_id == id)?;
+        Some(self.sources.remove(index).1)
+    }
+
+    /// Removes all point sources, plane wave sources and moving sources
+    pub fn clear_sources(&mut self) {
+        self.sources.clear();
+        self.plane_waves.clear();
+        self.moving_sources.clear();
+    }
+
+    /// Adds a source which drives a whole edge in every step, until it is removed
+    pub fn add_plane_wave(&mut self, source: PlaneWaveSource) -> SourceId {
+        let id = SourceId(self.next_source_id);
+        self.next_source_id += 1;
+        self.plane_waves.push((id, source));
+        id
+    }
+
+    pub fn plane_waves(&self) -> impl Iterator<Item = (SourceId, &PlaneWaveSource)> {
+        self.plane_waves.iter().map(|(id, source)| (*id, source))
+    }
+
+    pub fn plane_wave(&self, id: SourceId) -> Option<&PlaneWaveSource> {
+        self.plane_waves.iter().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn plane_wave_mut(&mut self, id: SourceId) -> Option<&mut PlaneWaveSource> {
+        self.plane_waves.iter_mut().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn remove_plane_wave(&mut self, id: SourceId) -> Option<PlaneWaveSource> {
+        let index = self.plane_waves.iter().position(|(source_id, _)| *source_id == id)?;
+        Some(self.plane_waves.remove(index).1)
+    }
+
+    /// Adds a source which travels along its path, until it is removed
+    pub fn add_moving_source(&mut self, source: MovingSource) -> SourceId {
+        let id = SourceId(self.next_source_id);
+        self.next_source_id += 1;
+        self.moving_sources.push((id, source));
+        id
+    }
+
+    pub fn moving_sources(&self) -> impl Iterator<Item = (SourceId, &MovingSource)> {
+        self.moving_sources.iter().map(|(id, source)| (*id, source))
+    }
+
+    pub fn moving_source(&self, id: SourceId) -> Option<&MovingSource> {
+        self.moving_sources.iter().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn moving_source_mut(&mut self, id: SourceId) -> Option<&mut MovingSource> {
+        self.moving_sources.iter_mut().find(|(source_id, _)| *source_id == id).map(|(_, source)| source)
+    }
+
+    pub fn remove_moving_source(&mut self, id: SourceId) -> Option<MovingSource> {
+        let index = self.moving_sources.iter().position(|(source_id, _)| *source_id == id)?;
+        Some(self.moving_sources.remove(index).1)
+    }
+
+    /// Returns the diagnostic if the simulation blew up, step does nothing until reset is called
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence
+    }
+
+    /// Sets the grid back to rest, keeps all parameters
+    pub fn reset(&mut self) {
+        self.record(InputEvent::Reset);
+        self.reset_fields();
+    }
+
+    // Sets the grid back to rest without recording it, for changes which install a new state afterwards
+    pub(super) fn reset_fields(&mut self) {
+        self.previous.fill(0.0);
+        self.current.fill(0.0);
+        self.next.fill(0.0);
+        self.forces.fill(0.0);
+        self.velocity.fill(0.0);
+        self.steps = 0;
+        self.divergence = None;
+        self.mouse_interupted = true;
+    }
+
+    pub fn boundary_conditions(&self) -> Boundaries {
+        self.boundaries
+    }
+
+    pub fn set_boundary_conditions(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+        self.update_sponge();
+    }
+
+    pub fn set_boundary_condition(&mut self, edge: Edge, condition: BoundaryCondition) {
+        self.boundaries.set(edge, condition);
+        self.update_sponge();
+    }
+
+    pub fn sponge_layer(&self) -> SpongeLayer {
+        self.sponge_layer
+    }
+
+    /// Sets the damping layer along all absorbing edges
+    pub fn set_sponge_layer(&mut self, sponge_layer: SpongeLayer) {
+        self.sponge_layer = sponge_layer;
+        self.update_sponge();
+    }
+
+    fn update_sponge(&mut self) {
+        self.sponge = self.boundaries.sponge_profile(self.sponge_layer, self.width, self.height);
+    }
+
+    // index of the node at x and y in the flat storage
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
     pub fn step(&mut self, substeps: Option<usize>) {
-        let delta_t = self.delta_t / substeps.unwrap_or(1) as f32;
+        self.record(InputEvent::Step { substeps });
+        if self.divergence.is_some() {
+            return;
+        }
+
+        self.apply_sources();
+
+        let substeps = substeps.unwrap_or(1);
+        let delta_t = self.delta_t / substeps as f32;
         let h = self.h;
+        let width = self.width;
+        let height = self.height;
+        let per_node_rows = self.per_node_rows();
+        self.substep_delta_t = delta_t;
 
-        let d = 0.998.pow(1. / substeps.unwrap_or(1) as f32) as f32 ;
-
-        for _i in 0..substeps.unwrap_or(1) {
-            #[allow(clippy::explicit_auto_deref)]
-            let previous: &[[f32; N]; M] = &*self.previous;
-            let current =  &*self.current;
-            let next = &mut *self.next;
-            for y in 0..M {
-                for x in 0..N {
-                    next[y][x] = d *(2.0 * current[y][x] - previous[y][x] + 
-                    (delta_t*delta_t) / (h*h) * 
-                    (current[y][x.saturating_add_signed(-1)] + 
-                    current[y][(x+1).min(N-1)] + 
-                    current[y.saturating_add_signed(-1)][x] + 
-                    current[(y+1).min(M-1)][x] - 
-                    4.0*current[y][x]
-                    + self.forces[y][x]));
-                }
+        for substep in 0..substeps {
+            let mut next = std::mem::take(&mut self.next);
+            let mut forces = std::mem::take(&mut self.forces);
+            // the forces act during all substeps and are cleared by the last one
+            let clear_forces = substep + 1 == substeps;
+            match self.integrator {
+                Integrator::Verlet => self.step_rows(&mut next, &mut forces, clear_forces, &per_node_rows, delta_t),
+                Integrator::Leapfrog => self.step_leapfrog(&mut next, &forces, &per_node_rows, delta_t),
+                Integrator::RungeKutta4 => self.step_runge_kutta_4(&mut next, &forces, &per_node_rows, delta_t),
+                Integrator::CrankNicolson => self.step_crank_nicolson(&mut next, &forces, &per_node_rows, delta_t),
+            }
+            if clear_forces && self.integrator != Integrator::Verlet {
+                forces.fill(0.0);
+            }
+            self.boundaries.apply(&self.current, &mut next, self.medium.wave_speed(), width, height, delta_t / h);
+            if self.integrator != Integrator::Verlet {
+                self.update_edge_velocity(&next, delta_t);
+            }
+            self.next = next;
+            self.forces = forces;
+
+            // keep the last valid state if the simulation blew up
+            if let Some(i) = self.next.iter().position(|u| !u.is_finite() || u.abs() > self.max_amplitude) {
+                self.divergence = Some(Divergence { 
+                    step: self.steps, 
+                    substep, 
+                    x: i % width, 
+                    y: i / width, 
+                    value: self.next[i], 
+                });
+                self.forces.fill(0.0);
+                return;
             }
             
             std::mem::swap(&mut self.previous,&mut self.current);
             std::mem::swap(&mut self.current,&mut self.next);
         }
-        for y in 0..M {
-            for x in 0..N {
-                self.forces[y][x] = 0.0;
+        self.steps += 1;
+    }
+
+    // Marks the rows which need the per node path, because the stencil reaches over an edge or into an obstacle
+    fn per_node_rows(&self) -> Vec<bool> {
+        let radius = self.stencil.radius();
+        if self.kernel == Kernel::PerNode || self.width <= 2 * radius {
+            return vec![true; self.height];
+        }
+
+        let obstacle_rows: Vec<bool> = self.obstacles.cells()
+            .chunks(self.width)
+            .map(|row| row.iter().any(|cell| cell.is_some()))
+            .collect();
+        (0..self.height).map(|y| {
+            y < radius || y + radius >= self.height ||
+            obstacle_rows[y - radius..=y + radius].iter().any(|obstacle| *obstacle)
+        }).collect()
+    }
+
+    // Calculates all nodes of the next time step, except the edges handled by the boundary conditions
+    fn step_rows(&self, next: &mut [f32], forces: &mut [f32], clear_forces: bool, per_node_rows: &[bool], delta_t: f32) {
+        let width = self.width;
+        cfg_if::cfg_if! {
+            if #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))] {
+                if self.threads > 1 {
+                    // each thread gets a block of consecutive rows, the nodes do not depend on each other
+                    let rows_per_thread = self.height.div_ceil(self.threads);
+                    std::thread::scope(|scope| {
+                        let blocks = next.chunks_mut(rows_per_thread * width).zip(forces.chunks_mut(rows_per_thread * width));
+                        for (block, (rows, forces)) in blocks.enumerate() {
+                            scope.spawn(move || {
+                                for (y, (row, forces)) in rows.chunks_mut(width).zip(forces.chunks_mut(width)).enumerate() {
+                                    let y = block * rows_per_thread + y;
+                                    self.step_row(y, row, forces, clear_forces, per_node_rows[y], delta_t);
+                                }
+                            });
+                        }
+                    });
+                    return;
+                }
             }
         }
+        for (y, (row, forces)) in next.chunks_mut(width).zip(forces.chunks_mut(width)).enumerate() {
+            self.step_row(y, row, forces, clear_forces, per_node_rows[y], delta_t);
+        }
+    }
 
+    // Calculates the row y of the next time step and clears its forces while they are in the cache
+    fn step_row(&self, y: usize, row: &mut [f32], forces: &mut [f32], clear_forces: bool, per_node: bool, delta_t: f32) {
+        let width = self.width;
+        let delta_t_h2 = (delta_t * delta_t) / (self.h * self.h);
+        if per_node {
+            for x in 0..width {
+                row[x] = self.step_node(x, y, forces[x], delta_t, delta_t_h2);
+            }
+        }
+        else {
+            let radius = self.stencil.radius();
+            for x in (0..radius).chain(width - radius..width) {
+                row[x] = self.step_node(x, y, forces[x], delta_t, delta_t_h2);
+            }
+
+            // interior, the laplacian is stored in next first
+            let begin = y * width + radius;
+            let end = (y + 1) * width - radius;
+            let next = &mut row[radius..width - radius];
+            kernel::interior_laplacian(self.stencil, &self.current, width, y, radius, next);
+
+            let len = next.len();
+            let current = &self.current[begin..end][..len];
+            let previous = &self.previous[begin..end][..len];
+            let damping = &self.damping[begin..end][..len];
+            let sponge = &self.sponge[begin..end][..len];
+            let wave_speed = &self.medium.wave_speed()[begin..end][..len];
+            let force = &forces[radius..width - radius][..len];
+            for x in 0..len {
+                let a = 0.5 * (damping[x] + sponge[x]) * delta_t;
+                next[x] = kernel::verlet(current[x], previous[x], next[x], wave_speed[x], force[x], a, delta_t_h2);
+            }
+        }
+
+        if clear_forces {
+            forces.fill(0.0);
+        }
+    }
+
+    // Calculates the node at x and y of the next time step, resolving boundary conditions and obstacles
+    fn step_node(&self, x: usize, y: usize, force: f32, delta_t: f32, delta_t_h2: f32) -> f32 {
+        let i = self.index(x, y);
+        if self.obstacles.cells()[i].is_some() {
+            return 0.0;
+        }
+        let a = 0.5 * (self.damping[i] + self.sponge[i]) * delta_t;
+        kernel::verlet(self.current[i], self.previous[i], self.laplacian(&self.current, x, y), self.medium.wave_speed()[i], force, a, delta_t_h2)
+    }
+
+    // Stencil of the field u at x and y, without the factor 1/h^2
+    fn laplacian(&self, u: &[f32], x: usize, y: usize) -> f32 {
+        let center = u[self.index(x, y)];
+        let x = x as isize;
+        let y = y as isize;
+
+        let mut laplacian = 0.0;
+        for (d_x, d_y, weight) in self.stencil.neighbours() {
+            // the stencil stops at the first obstacle node, so it never reads through thin walls
+            let (x_n, y_n) = (x + d_x.signum(), y + d_y.signum());
+            laplacian += if self.covered(x_n, y_n) {
+                weight * self.neighbour(u, center, x_n, y_n)
+            } else {
+                weight * self.neighbour(u, center, x + d_x, y + d_y)
+            };
+        }
+        laplacian + self.stencil.center() * center
+    }
+
+    // Value of the field u at x and y next to a node with the value center
+    // The node may lie outside of the grid or within an obstacle
+    fn neighbour(&self, u: &[f32], center: f32, x: isize, y: isize) -> f32 {
+        match self.boundaries.resolve_node(self.width, self.height, x, y) {
+            Some((i, sign)) => match self.obstacles.cells()[i] {
+                None => sign * u[i],
+                Some(Obstacle::Fixed) => 0.0,
+                // mirror the node at the surface of the obstacle
+                Some(Obstacle::Free) => center,
+            },
+            None => 0.0,
+        }
+    }
+
+    // If the node at x and y lies within an obstacle, the node may lie outside of the grid
+    fn covered(&self, x: isize, y: isize) -> bool {
+        self.boundaries.resolve_node(self.width, self.height, x, y)
+            .is_some_and(|(i, _sign)| self.obstacles.cells()[i].is_some())
     }
 
     // Adds forces to the position of y and x to the grid
     pub fn add_impulse(&mut self, y:f32, x:f32) {
+        self.record(InputEvent::Impulse { y, x });
         // check bounds
-        if y <= 0.0 || y >= (M-1) as f32 ||
-        x <= 0.0 || x >= (N-1) as f32 {
+        if y <= 0.0 || y >= (self.height-1) as f32 ||
+        x <= 0.0 || x >= (self.width-1) as f32 {
             self.mouse_interupted = true;
             return;
         }
@@ -89,21 +676,7 @@ impl<const M:usize, const N:usize>  WaveEquation<M, N>{
         let same_quad = x.floor() == self.x_old.floor() && y.floor() == self.y_old.floor();
 
         if self.mouse_interupted || same_quad {
-            let w_x = x - x.floor();
-            let w_y = y - y.floor();
-            let x_i = x.floor() as usize;
-            let y_i = y.floor() as usize;
-
-            //Add the force to position
-            // self.forces[y_i][x_i] += force_strength * (1.-w_y) * (1.-w_x);
-            // self.forces[y_i+1][x_i] += force_strength * (w_y) * (1.-w_x);
-            // self.forces[y_i][x_i+1] += force_strength * (1.-w_y) * (w_x);
-            // self.forces[y_i+1][x_i+1] += force_strength * (w_y) * (w_x);
-
-            self.add_smoothed_force_to_point(x_i, y_i, force_strength * (1.-w_y) * (1.-w_x));
-            self.add_smoothed_force_to_point(x_i, y_i+1, force_strength * (w_y) * (1.-w_x));
-            self.add_smoothed_force_to_point(x_i+1, y_i, force_strength * (1.-w_y) * (w_x));
-            self.add_smoothed_force_to_point(x_i+1, y_i+1, force_strength * (w_y) * (w_x));
+            self.add_point_force(x, y, force_strength);
         } else {
             self.add_line_force(x, y, force_strength);
         }
@@ -113,17 +686,25 @@ impl<const M:usize, const N:usize>  WaveEquation<M, N>{
     }
 
     fn add_line_force(&mut self, x_new: f32, y_new: f32, force: f32) {
-        let mut x_0= self.x_old;
-        let mut y_0= self.y_old;
-        let mut x_1= x_new;
-        let mut y_1= y_new;
+        let total_lenght = ((x_new - self.x_old) * (x_new - self.x_old) + (y_new - self.y_old) * (y_new - self.y_old)).sqrt();
+        // apply full force along the line
+        self.add_segment_force(self.x_old, self.y_old, x_new, y_new, force, total_lenght.sqrt().min(total_lenght));
+    }
+
+    // Distributes a force along the line from x_0, y_0 to x_1, y_1 to the nodes of the crossed squares,
+    // each square gets the force times the length of the line within it divided by the given length,
+    // both ends have to lie inside the grid
+    fn add_segment_force(&mut self, x_0: f32, y_0: f32, x_1: f32, y_1: f32, force: f32, total_lenght: f32) {
+        let mut x_0 = x_0;
+        let mut y_0 = y_0;
+        let mut x_1 = x_1;
+        let mut y_1 = y_1;
         // Start from lower x end
-        if x_new < self.x_old {
+        if x_1 < x_0 {
             std::mem::swap(&mut x_0, &mut x_1);
             std::mem::swap(&mut y_0, &mut y_1);
         }
 
-        let total_lenght = ((x_1 - x_0) * (x_1 - x_0) + (y_1 - y_0) * (y_1 - y_0)).sqrt();
         let inclination = (y_1 - y_0) / (x_1 - x_0);
 
         // index of the current and final square
@@ -158,8 +739,8 @@ impl<const M:usize, const N:usize>  WaveEquation<M, N>{
             for _j in 0..steps {
                 x_cut = 1.;
                 y_cut = y_curr + inclination * (x_cut - x_curr);
-                //successful cut on right border
-                if y_cut < 1. && y_cut > 0. {
+                //successful cut on right border, lines along a border never cut the top or bottom
+                if (y_cut < 1. && y_cut > 0.) || inclination == 0. {
                     self.add_line_force_to_square(x_i, y_i, total_lenght, force, x_curr, y_curr, x_cut, y_cut);
                     x_i += 1;
                     x_cut = 0.;
@@ -192,9 +773,7 @@ impl<const M:usize, const N:usize>  WaveEquation<M, N>{
     fn add_line_force_to_square(&mut self, x_i: usize, y_i: usize, total_lenght: f32, force: f32, x_in: f32, y_in: f32, x_out: f32, y_out: f32) {
         let length = f32::sqrt((x_out-x_in) * (x_out-x_in) + (y_out-y_in) * (y_out-y_in));
         
-        // let factor = length * force / total_lenght;           // scale force with length
-        // let factor = length * force / total_lenght.min(1.); // apply full force along the line
-        let factor = length * force / total_lenght.sqrt().min(total_lenght); // apply full force along the line
+        let factor = length * force / total_lenght;
 
         // self.forces[y_i][x_i]       += factor * self.unit_square_integral(1.-x_in, 1.-y_in, 1.-x_out, 1.-y_out);
         // self.forces[y_i+1][x_i]     += factor * self.unit_square_integral(1.-x_in, y_in, 1.-x_out, y_out);
@@ -212,32 +791,287 @@ impl<const M:usize, const N:usize>  WaveEquation<M, N>{
         (2. * x_in * y_in + x_in * y_out + y_in * x_out + 2. * x_out * y_out) / 6.
     }
 
+    // Distributes the force at x and y bilinearly to the surrounding nodes, the position has to lie inside the grid
+    fn add_point_force(&mut self, x: f32, y: f32, force: f32) {
+        let w_x = x - x.floor();
+        let w_y = y - y.floor();
+        let x_i = x.floor() as usize;
+        let y_i = y.floor() as usize;
+
+        //Add the force to position
+        // self.forces[y_i][x_i] += force * (1.-w_y) * (1.-w_x);
+        // self.forces[y_i+1][x_i] += force * (w_y) * (1.-w_x);
+        // self.forces[y_i][x_i+1] += force * (1.-w_y) * (w_x);
+        // self.forces[y_i+1][x_i+1] += force * (w_y) * (w_x);
+
+        self.add_smoothed_force_to_point(x_i, y_i, force * (1.-w_y) * (1.-w_x));
+        self.add_smoothed_force_to_point(x_i, y_i+1, force * (w_y) * (1.-w_x));
+        self.add_smoothed_force_to_point(x_i+1, y_i, force * (1.-w_y) * (w_x));
+        self.add_smoothed_force_to_point(x_i+1, y_i+1, force * (w_y) * (w_x));
+    }
+
+    // Adds the forces of all sources at the current time, sources outside of the grid are ignored
+    fn apply_sources(&mut self) {
+        let t = self.time();
+        for i in 0..self.sources.len() {
+            let source = self.sources[i].1;
+            if source.x <= 0.0 || source.x >= (self.width-1) as f32 ||
+            source.y <= 0.0 || source.y >= (self.height-1) as f32 {
+                continue;
+            }
+            self.add_point_force(source.x, source.y, source.force(t));
+        }
+
+        // plane waves drive the first line inside the edge, the edge itself belongs to the boundary condition
+        let (width, height) = (self.width, self.height);
+        for i in 0..self.plane_waves.len() {
+            let source = self.plane_waves[i].1;
+            let force = source.force(t);
+            match source.edge {
+                Edge::Left => (0..height).for_each(|y| self.add_force(1, y, force)),
+                Edge::Right => (0..height).for_each(|y| self.add_force(width - 2, y, force)),
+                Edge::Bottom => (0..width).for_each(|x| self.add_force(x, 1, force)),
+                Edge::Top => (0..width).for_each(|x| self.add_force(x, height - 2, force)),
+            }
+        }
+
+        // moving sources spread their force along the distance travelled during the step
+        let inside = |(x, y): (f32, f32)| x > 0.0 && x < (width-1) as f32 && y > 0.0 && y < (height-1) as f32;
+        for i in 0..self.moving_sources.len() {
+            let source = &self.moving_sources[i].1;
+            let force = source.force(t);
+            let (x_0, y_0) = source.position(t, self.h);
+            let (x_1, y_1) = source.position(t + self.delta_t, self.h);
+            if !inside((x_0, y_0)) || !inside((x_1, y_1)) {
+                continue;
+            }
+            let length = (x_1 - x_0).hypot(y_1 - y_0);
+            if length < 1e-3 {
+                self.add_point_force(x_0, y_0, force);
+            } else {
+                self.add_segment_force(x_0, y_0, x_1, y_1, force, length);
+            }
+        }
+    }
+
     // Adds a force to a given mesh node spread over a 3x3 stencil
     // Only the specified node needs to be valid, not its neighbors
     fn add_smoothed_force_to_point(&mut self, x_i: usize, y_i: usize, force: f32) {
         let force16 = force / 16.;
 
-        self.forces[y_i.saturating_add_signed(-1)][x_i.saturating_add_signed(-1)] += force16 * 1.;
-        self.forces[y_i.saturating_add_signed(-1)][x_i] += force16 * 2.;
-        self.forces[y_i.saturating_add_signed(-1)][(x_i+1).min(N-1)] += force16 * 1.;
+        let x_left = x_i.saturating_add_signed(-1);
+        let x_right = (x_i+1).min(self.width-1);
+        let y_down = y_i.saturating_add_signed(-1);
+        let y_up = (y_i+1).min(self.height-1);
 
-        self.forces[y_i][x_i.saturating_add_signed(-1)] += force16 * 2.;
-        self.forces[y_i][x_i] += force16 * 4.;
-        self.forces[y_i][(x_i+1).min(N-1)] += force16 * 2.;
+        self.add_force(x_left, y_down, force16 * 1.);
+        self.add_force(x_i, y_down, force16 * 2.);
+        self.add_force(x_right, y_down, force16 * 1.);
 
-        self.forces[(y_i+1).min(M-1)][x_i.saturating_add_signed(-1)] += force16 * 1.;
-        self.forces[(y_i+1).min(M-1)][x_i] += force16 * 2.;
-        self.forces[(y_i+1).min(M-1)][(x_i+1).min(N-1)] += force16 * 1.;
+        self.add_force(x_left, y_i, force16 * 2.);
+        self.add_force(x_i, y_i, force16 * 4.);
+        self.add_force(x_right, y_i, force16 * 2.);
+
+        self.add_force(x_left, y_up, force16 * 1.);
+        self.add_force(x_i, y_up, force16 * 2.);
+        self.add_force(x_right, y_up, force16 * 1.);
+    }
+
+    fn add_force(&mut self, x: usize, y: usize, force: f32) {
+        let i = self.index(x, y);
+        self.forces[i] += force;
     }
 
     //Tells the class, that mouse is no longer continuously clicked
-    pub fn interupt_mouse(&mut self) {self.mouse_interupted = true}
+    pub fn interupt_mouse(&mut self) {
+        self.record(InputEvent::InterruptMouse);
+        self.mouse_interupted = true
+    }
 
-    //returns a reference to the current wave grid
-    pub fn get_current(&self) -> &[[f32; N]; M] {
+    //returns a reference to the current wave grid, stored row by row
+    pub fn get_current(&self) -> &[f32] {
         &self.current
     }
 
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 40;
+
+    // Places a gaussian bump at rest, shared by the tests of the submodules
+    pub(super) fn add_bump(wave_equation: &mut WaveEquation, x_0: f32, y_0: f32) {
+        let width = wave_equation.width();
+        for y in 0..wave_equation.height() {
+            for x in 0..width {
+                let r2 = (x as f32 - x_0).powi(2) + (y as f32 - y_0).powi(2);
+                let val = (-r2 / 8.0).exp();
+                wave_equation.current[y * width + x] += val;
+                wave_equation.previous[y * width + x] += val;
+            }
+        }
+    }
+
+    // Energy of the Verlet scheme, which is exactly conserved without damping on a periodic grid
+    fn energy(wave_equation: &WaveEquation, delta_t: f32) -> f32 {
+        let u = &wave_equation.current;
+        let u_old = &wave_equation.previous;
+        let h = wave_equation.h;
+        let mut energy = 0.0;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let i = y * WIDTH + x;
+                let right = y * WIDTH + (x + 1) % WIDTH;
+                let up = ((y + 1) % HEIGHT) * WIDTH + x;
+                let velocity = (u[i] - u_old[i]) / delta_t;
+                let potential = ((u[right] - u[i]) * (u_old[right] - u_old[i]) + (u[up] - u[i]) * (u_old[up] - u_old[i])) / (h * h);
+                energy += 0.5 * (velocity * velocity + potential);
+            }
+        }
+        energy
+    }
+
+    // Amplitude relative to the start after 1 second
+    fn relative_amplitude(damping: f32, substeps: usize) -> f32 {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Periodic));
+        wave_equation.set_damping(damping);
+        add_bump(&mut wave_equation, 20.0, 20.0);
+        let delta_t = wave_equation.delta_t / substeps as f32;
+
+        // the bump is at rest, so the energy equals the energy for any time step
+        let energy_start = energy(&wave_equation, delta_t);
+        let steps = (1.0 / wave_equation.delta_t).round() as usize;
+        for _i in 0..steps {
+            wave_equation.step(Some(substeps));
+        }
+
+        (energy(&wave_equation, delta_t) / energy_start).sqrt()
+    }
+
+    #[test]
+    fn strokes_along_grid_lines_stay_finite() {
+        // a horizontal stroke on a row of nodes never cuts the top or bottom of a square,
+        // which divided zero by the inclination of zero
+        let mut wave_equation = WaveEquation::new(20, 20);
+        wave_equation.add_impulse(10.0, 5.5);
+        wave_equation.add_impulse(10.0, 8.5);
+        assert!(wave_equation.forces.iter().all(|force| force.is_finite()));
+        assert!(wave_equation.forces[10 * 20 + 7] > 0.0);
+
+        wave_equation.step(None);
+        assert!(wave_equation.divergence().is_none());
+    }
+
+    #[test]
+    fn unstable_parameters_are_rejected() {
+        let mut config = WaveEquationConfig::new(WIDTH, HEIGHT);
+        assert!(WaveEquation::with_config(config).is_ok());
+
+        config.delta_t = 0.1;
+        assert!(matches!(WaveEquation::with_config(config), Err(WaveEquationError::Unstable { .. })));
+
+        // substeps reduce the time step, but construction only accepts parameters stable without substeps
+        config.delta_t = 0.05;
+        config.wave_speed = 2.0;
+        assert!(matches!(WaveEquation::with_config(config), Err(WaveEquationError::Unstable { .. })));
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let config = WaveEquationConfig::new(WIDTH, HEIGHT);
+        assert_eq!(
+            WaveEquation::with_config(WaveEquationConfig { width: 2, ..config }).err(), 
+            Some(WaveEquationError::InvalidGridSize { width: 2, height: HEIGHT }));
+        assert_eq!(
+            WaveEquation::with_config(WaveEquationConfig { h: 0.0, ..config }).err(), 
+            Some(WaveEquationError::InvalidParameter { name: "h", value: 0.0 }));
+        assert!(WaveEquation::with_config(WaveEquationConfig { damping: f32::NAN, ..config }).is_err());
+    }
+
+    #[test]
+    fn blow_up_is_detected() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.medium_mut().fill(Shape::Circle { x: 20.0, y: 20.0, radius: 5.0 }, 3.0);
+        assert!(wave_equation.check_stability().is_err());
+        add_bump(&mut wave_equation, 20.0, 20.0);
+
+        for _i in 0..200 {
+            wave_equation.step(None);
+        }
+
+        let divergence = wave_equation.divergence().expect("no blow up detected");
+        assert!(divergence.step < 200);
+        assert_eq!(wave_equation.steps(), divergence.step);
+        assert!(wave_equation.medium().get(divergence.x, divergence.y) == 3.0);
+        assert!(wave_equation.get_current().iter().all(|u| u.is_finite()));
+
+        wave_equation.reset();
+        assert_eq!(wave_equation.divergence(), None);
+        assert!(wave_equation.get_current().iter().all(|u| *u == 0.0));
+    }
+
+    #[test]
+    fn damping_is_independent_of_substeps() {
+        let amplitude = relative_amplitude(1.0, 1);
+        assert!(amplitude < 0.9);
+
+        for substeps in [2, 8] {
+            let amplitude_substeps = relative_amplitude(1.0, substeps);
+            assert!((amplitude_substeps - amplitude).abs() < 0.01 * amplitude, "{substeps} substeps: {amplitude_substeps} instead of {amplitude}");
+        }
+    }
+
+    #[test]
+    fn no_damping_conserves_amplitude() {
+        for substeps in [1, 2, 8] {
+            assert!((relative_amplitude(0.0, substeps) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    // Runs a scene with obstacles, a varying medium, absorbing edges and mouse forces
+    fn run_scene(wave_equation: &mut WaveEquation, stencil: Stencil, threads: usize) -> Vec<f32> {
+        wave_equation.reset();
+        wave_equation.set_threads(threads);
+        wave_equation.set_stencil(stencil);
+        wave_equation.set_boundary_condition(Edge::Left, BoundaryCondition::Absorbing);
+        wave_equation.set_boundary_condition(Edge::Top, BoundaryCondition::Periodic);
+        wave_equation.set_boundary_condition(Edge::Bottom, BoundaryCondition::Periodic);
+        wave_equation.medium_mut().fill(Shape::Circle { x: 25.0, y: 20.0, radius: 6.0 }, 0.6);
+        wave_equation.obstacles_mut().stamp_circle(32.0, 30.0, 2.0, Obstacle::Fixed);
+        add_bump(wave_equation, 12.0, 20.0);
+
+        for i in 0..60 {
+            wave_equation.add_impulse(10.0 + 0.2 * i as f32, 15.0);
+            wave_equation.step(Some(2));
+        }
+        wave_equation.get_current().to_vec()
+    }
+
+    #[test]
+    fn kernels_give_identical_results() {
+        let mut per_node = WaveEquation::new(WIDTH, HEIGHT);
+        per_node.set_kernel(Kernel::PerNode);
+        let mut interior = WaveEquation::new(WIDTH, HEIGHT);
+        interior.set_kernel(Kernel::Interior);
+        for stencil in [Stencil::FivePoint, Stencil::NinePoint, Stencil::FourthOrder] {
+            assert_eq!(run_scene(&mut per_node, stencil, 1), run_scene(&mut interior, stencil, 1));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn threads_give_identical_results() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let serial = run_scene(&mut wave_equation, Stencil::NinePoint, 1);
+        // 3 threads do not divide the 40 rows evenly
+        for threads in [2, 3, 8] {
+            let parallel = run_scene(&mut wave_equation, Stencil::NinePoint, threads);
+            assert!(serial.iter().zip(&parallel).all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+}
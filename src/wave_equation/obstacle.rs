@@ -0,0 +1,189 @@
+//! Obstacles placed into the pool, e.g. walls with slits for ripple tank experiments
+//!
+
+use super::Shape;
+
+/// Behaviour of the nodes covered by an obstacle
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Obstacle {
+    /// The node is pinned to zero displacement, waves are reflected inverted
+    Fixed,
+    /// The surface of the obstacle has zero normal derivative, waves are reflected upright
+    Free,
+}
+
+/// Marks the nodes of the grid covered by obstacles
+#[derive(Clone)]
+pub struct ObstacleMask {
+    width: usize,
+    height: usize,
+
+    cells: Vec<Option<Obstacle>>,
+}
+
+impl ObstacleMask {
+    /// Creates an empty mask
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![None; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Obstacle> {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, obstacle: Option<Obstacle>) {
+        self.cells[y * self.width + x] = obstacle;
+    }
+
+    /// Returns the obstacles of all nodes, stored row by row
+    pub fn cells(&self) -> &[Option<Obstacle>] {
+        &self.cells
+    }
+
+    /// Removes all obstacles
+    pub fn clear(&mut self) {
+        self.cells.fill(None);
+    }
+
+    /// Marks all nodes within the shape, None removes the obstacles again
+    pub fn stamp(&mut self, shape: Shape, obstacle: Option<Obstacle>) {
+        let width = self.width;
+        shape.for_each_node(self.width, self.height, |x, y| self.cells[y * width + x] = obstacle);
+    }
+
+    pub fn stamp_rectangle(&mut self, x_0: f32, y_0: f32, x_1: f32, y_1: f32, obstacle: Obstacle) {
+        self.stamp(Shape::Rectangle { x_0, y_0, x_1, y_1 }, Some(obstacle));
+    }
+
+    pub fn stamp_circle(&mut self, x: f32, y: f32, radius: f32, obstacle: Obstacle) {
+        self.stamp(Shape::Circle { x, y, radius }, Some(obstacle));
+    }
+
+    /// Walls which are not aligned with the grid axes need a thickness of at least 2 to be watertight
+    pub fn stamp_line(&mut self, x_0: f32, y_0: f32, x_1: f32, y_1: f32, thickness: f32, obstacle: Obstacle) {
+        self.stamp(Shape::Line { x_0, y_0, x_1, y_1, thickness }, Some(obstacle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Stencil, WaveEquation};
+    use super::super::tests::add_bump;
+
+    const WIDTH: usize = 60;
+    const HEIGHT: usize = 40;
+
+    // Sum of u^2 right of the wall at x = 30
+    fn energy_behind_wall(wave_equation: &WaveEquation) -> f32 {
+        let mut energy = 0.0;
+        for y in 0..HEIGHT {
+            for x in 31..WIDTH {
+                let u = wave_equation.get_current()[y * WIDTH + x];
+                energy += u * u;
+            }
+        }
+        energy
+    }
+
+    #[test]
+    fn stamps_cover_expected_nodes() {
+        let mut mask = ObstacleMask::new(WIDTH, HEIGHT);
+        mask.stamp_rectangle(2.0, 3.0, 4.0, 7.0, Obstacle::Fixed);
+        assert_eq!(mask.cells().iter().filter(|cell| cell.is_some()).count(), 3 * 5);
+
+        mask.clear();
+        mask.stamp_circle(20.0, 20.0, 1.0, Obstacle::Free);
+        assert_eq!(mask.cells().iter().filter(|cell| cell.is_some()).count(), 5);
+        assert_eq!(mask.get(21, 20), Some(Obstacle::Free));
+
+        mask.clear();
+        mask.stamp_line(10.0, 0.0, 10.0, 39.0, 1.0, Obstacle::Fixed);
+        assert_eq!(mask.cells().iter().filter(|cell| cell.is_some()).count(), HEIGHT);
+        assert!((0..HEIGHT).all(|y| mask.get(10, y) == Some(Obstacle::Fixed)));
+    }
+
+    #[test]
+    fn obstacle_nodes_stay_at_zero() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        add_bump(&mut wave_equation, 20.0, 20.0);
+        wave_equation.obstacles_mut().stamp_circle(20.0, 20.0, 3.0, Obstacle::Fixed);
+        wave_equation.obstacles_mut().stamp_circle(30.0, 20.0, 3.0, Obstacle::Free);
+
+        for _i in 0..50 {
+            wave_equation.add_impulse(20.5, 20.5);
+            wave_equation.step(None);
+            for (u, cell) in wave_equation.get_current().iter().zip(wave_equation.obstacles().cells()) {
+                if cell.is_some() {
+                    assert_eq!(*u, 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn closed_wall_blocks_waves() {
+        for obstacle in [Obstacle::Fixed, Obstacle::Free] {
+            let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+            wave_equation.obstacles_mut().stamp_line(30.0, 0.0, 30.0, (HEIGHT - 1) as f32, 1.0, obstacle);
+            add_bump(&mut wave_equation, 15.0, 20.0);
+
+            for _i in 0..100 {
+                wave_equation.step(None);
+            }
+            // only the tail of the initial gaussian remains
+            assert!(energy_behind_wall(&wave_equation) < 1e-20);
+        }
+    }
+
+    #[test]
+    fn fourth_order_stops_at_thin_walls() {
+        for obstacle in [Obstacle::Fixed, Obstacle::Free] {
+            let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+            wave_equation.set_stencil(Stencil::FourthOrder);
+            wave_equation.obstacles_mut().stamp_line(30.0, 0.0, 30.0, (HEIGHT - 1) as f32, 1.0, obstacle);
+            add_bump(&mut wave_equation, 15.0, 20.0);
+            // the field behind the wall starts exactly at zero and has to stay there
+            for y in 0..HEIGHT {
+                for x in 31..WIDTH {
+                    wave_equation.current[y * WIDTH + x] = 0.0;
+                    wave_equation.previous[y * WIDTH + x] = 0.0;
+                }
+            }
+
+            for _i in 0..100 {
+                wave_equation.step(None);
+            }
+            assert_eq!(energy_behind_wall(&wave_equation), 0.0);
+        }
+    }
+
+    #[test]
+    fn waves_pass_double_slit() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let mask = wave_equation.obstacles_mut();
+        mask.stamp_line(30.0, 0.0, 30.0, (HEIGHT - 1) as f32, 1.0, Obstacle::Fixed);
+        mask.stamp(Shape::Rectangle { x_0: 30.0, y_0: 15.0, x_1: 30.0, y_1: 16.0 }, None);
+        mask.stamp(Shape::Rectangle { x_0: 30.0, y_0: 23.0, x_1: 30.0, y_1: 24.0 }, None);
+        add_bump(&mut wave_equation, 15.0, 20.0);
+
+        for _i in 0..100 {
+            wave_equation.step(None);
+        }
+        assert!(energy_behind_wall(&wave_equation) > 1e-3);
+    }
+}
@@ -0,0 +1,219 @@
+//! Built-in experiments, which set up the obstacles, sources and the medium of a wave equation
+//!
+//! The layouts scale with the grid size, the waves have a wavelength of 16 nodes.
+//!
+
+use super::Boundaries;
+use super::BoundaryCondition;
+use super::Edge;
+use super::Medium;
+use super::Obstacle;
+use super::PlaneWaveSource;
+use super::PointSource;
+use super::Shape;
+use super::SpongeLayer;
+use super::WaveEquation;
+use super::Waveform;
+
+// wavelength in nodes of the sources in the medium with the highest wave speed
+const WAVELENGTH: f32 = 16.0;
+
+/// A classic experiment of wave optics
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Preset {
+    /// Plane wave through two narrow slits
+    DoubleSlit,
+    /// Plane wave through one slit of about the wavelength
+    SingleSlit,
+    /// Plane wave through a wall with many equally spaced slits
+    DiffractionGrating,
+    /// Plane wave focused by a biconvex lens of a slower medium
+    FocusingLens,
+    /// Plane wave focused by a parabolic mirror
+    ParabolicReflector,
+    /// Point source in a stripe of slower medium, which guides the waves by total internal reflection
+    Waveguide,
+    /// Two point sources in phase
+    YoungsInterference,
+    /// Eigenmode of a box with fixed walls, which oscillates in place
+    StandingWaves,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 8] = [
+        Preset::DoubleSlit,
+        Preset::SingleSlit,
+        Preset::DiffractionGrating,
+        Preset::FocusingLens,
+        Preset::ParabolicReflector,
+        Preset::Waveguide,
+        Preset::YoungsInterference,
+        Preset::StandingWaves,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::DoubleSlit => "double slit",
+            Preset::SingleSlit => "single slit",
+            Preset::DiffractionGrating => "diffraction grating",
+            Preset::FocusingLens => "focusing lens",
+            Preset::ParabolicReflector => "parabolic reflector",
+            Preset::Waveguide => "waveguide",
+            Preset::YoungsInterference => "young's interference",
+            Preset::StandingWaves => "standing waves",
+        }
+    }
+
+    /// The following preset, starts over after the last one
+    pub fn next(&self) -> Preset {
+        let index = Self::ALL.iter().position(|preset| preset == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl WaveEquation {
+    /// Sets the grid back to rest and replaces the obstacles, sources, medium and boundaries with the preset
+    /// Keeps the damping and the highest wave speed of the medium, so the time step stays stable
+    pub fn apply_preset(&mut self, preset: Preset) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let c = self.medium.max_wave_speed();
+        let frequency = c / (WAVELENGTH * self.h);
+
+        // the new state can not be replayed, so the recorded input ends here
+        self.close_recording();
+        self.reset_fields();
+        self.clear_sources();
+        self.obstacles.clear();
+        self.medium = Medium::new(self.width, self.height, c);
+        self.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Absorbing));
+        self.set_sponge_layer(SpongeLayer::default());
+
+        let x_c = 0.5 * (width - 1.0);
+        let y_wall = (0.35 * height).round();
+        match preset {
+            Preset::DoubleSlit => {
+                self.add_incoming_plane_wave(frequency);
+                self.add_wall(y_wall, &[x_c - 1.5 * WAVELENGTH, x_c + 1.5 * WAVELENGTH], 0.5 * WAVELENGTH);
+            },
+            Preset::SingleSlit => {
+                self.add_incoming_plane_wave(frequency);
+                self.add_wall(y_wall, &[x_c], 1.5 * WAVELENGTH);
+            },
+            Preset::DiffractionGrating => {
+                self.add_incoming_plane_wave(frequency);
+                let period = 1.5 * WAVELENGTH;
+                let count = (width / period) as usize;
+                let slits: Vec<f32> = (0..count).map(|i| x_c + (i as f32 - 0.5 * (count - 1) as f32) * period).collect();
+                self.add_wall(y_wall, &slits, 0.25 * WAVELENGTH);
+            },
+            Preset::FocusingLens => {
+                self.add_incoming_plane_wave(frequency);
+                // intersection of two circles, the focal length of about the radius with n = 1.5
+                let radius = 0.5 * width;
+                let thickness = 0.15 * width;
+                let y_lens = 0.3 * height;
+                let front = Shape::Circle { x: x_c, y: y_lens + radius - 0.5 * thickness, radius };
+                let back = Shape::Circle { x: x_c, y: y_lens - radius + 0.5 * thickness, radius };
+                let medium = &mut self.medium;
+                front.for_each_node(self.width, self.height, |x, y| {
+                    if back.contains(x as f32, y as f32) {
+                        medium.set(x, y, c / 1.5);
+                    }
+                });
+            },
+            Preset::ParabolicReflector => {
+                self.add_incoming_plane_wave(frequency);
+                // mirror y = y_vertex - (x - x_c)^2 / (4 f), the focus lies f below the vertex
+                let focal_length = 0.25 * width;
+                let y_vertex = 0.85 * height;
+                let parabola = |x: f32| y_vertex - (x - x_c).powi(2) / (4.0 * focal_length);
+                let segments = 40;
+                let x_0 = x_c - 0.4 * width;
+                let length = 0.8 * width / segments as f32;
+                for i in 0..segments {
+                    let x_1 = x_0 + i as f32 * length;
+                    let x_2 = x_1 + length;
+                    self.obstacles.stamp_line(x_1, parabola(x_1), x_2, parabola(x_2), 2.0, Obstacle::Fixed);
+                }
+            },
+            Preset::Waveguide => {
+                self.set_sponge_layer(SpongeLayer { thickness: 10, strength: 2.0 });
+                let core = Shape::Rectangle { x_0: x_c - 0.5 * WAVELENGTH, y_0: 0.0, x_1: x_c + 0.5 * WAVELENGTH, y_1: height - 1.0 };
+                self.medium.fill(core, c / 1.5);
+                self.add_source(PointSource::new(x_c, 0.15 * height, frequency, 4.0));
+            },
+            Preset::YoungsInterference => {
+                self.set_sponge_layer(SpongeLayer { thickness: 10, strength: 2.0 });
+                for x in [x_c - 1.5 * WAVELENGTH, x_c + 1.5 * WAVELENGTH] {
+                    self.add_source(PointSource::new(x, 0.3 * height, frequency, 4.0));
+                }
+            },
+            Preset::StandingWaves => {
+                self.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Fixed));
+                // 3 half waves along x and 4 along y, an eigenvector of the discrete laplacian
+                use std::f32::consts::PI;
+                let displacement: Vec<f32> = (0..self.width * self.height).map(|i| {
+                    let x = (i % self.width) as f32 / (width - 1.0);
+                    let y = (i / self.width) as f32 / (height - 1.0);
+                    (3.0 * PI * x).sin() * (4.0 * PI * y).sin()
+                }).collect();
+                self.set_initial_displacement(&displacement);
+            },
+        }
+    }
+
+    // Plane wave which enters the grid at the bottom edge
+    fn add_incoming_plane_wave(&mut self, frequency: f32) {
+        self.add_plane_wave(PlaneWaveSource::new(Edge::Bottom, Waveform::sine(frequency), 0.5));
+    }
+
+    // Wall across the grid at the height y, with openings of slit_width around the centers of the slits
+    fn add_wall(&mut self, y: f32, slits: &[f32], slit_width: f32) {
+        self.obstacles.stamp_rectangle(0.0, y, (self.width - 1) as f32, y + 2.0, Obstacle::Fixed);
+        for x in slits {
+            self.obstacles.stamp(Shape::Rectangle { x_0: x - 0.5 * slit_width, y_0: y, x_1: x + 0.5 * slit_width, y_1: y + 2.0 }, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_run_without_blow_up() {
+        let mut wave_equation = WaveEquation::new(140, 160);
+        for preset in Preset::ALL {
+            wave_equation.apply_preset(preset);
+            for _i in 0..500 {
+                wave_equation.step(None);
+            }
+            let max_amplitude = wave_equation.diagnostics().max_amplitude;
+            assert!(wave_equation.divergence().is_none(), "{} diverged", preset.name());
+            assert!(max_amplitude > 0.01 && max_amplitude < 10.0, "{}: max amplitude {max_amplitude}", preset.name());
+        }
+    }
+
+    #[test]
+    fn presets_replace_the_previous_setup() {
+        let mut wave_equation = WaveEquation::new(140, 160);
+        wave_equation.apply_preset(Preset::DoubleSlit);
+        let y_wall = 56;
+        let blocked = |wave_equation: &WaveEquation, x| wave_equation.obstacles().get(x, y_wall).is_some();
+        assert!(blocked(&wave_equation, 0) && blocked(&wave_equation, 69));
+        assert!(!blocked(&wave_equation, 69 - 24) && !blocked(&wave_equation, 70 + 24));
+        assert_eq!(wave_equation.plane_waves().count(), 1);
+
+        wave_equation.step(None);
+        wave_equation.apply_preset(Preset::YoungsInterference);
+        assert_eq!(wave_equation.steps(), 0);
+        assert!(wave_equation.obstacles().cells().iter().all(|cell| cell.is_none()));
+        assert_eq!(wave_equation.plane_waves().count(), 0);
+        assert_eq!(wave_equation.sources().count(), 2);
+
+        assert_eq!(Preset::StandingWaves.next(), Preset::DoubleSlit);
+        assert_eq!(Preset::ALL.iter().map(|preset| preset.next()).filter(|preset| *preset == Preset::DoubleSlit).count(), 1);
+    }
+}
@@ -0,0 +1,176 @@
+//! Virtual probes which record the displacement at a point of the grid
+//!
+
+use std::collections::VecDeque;
+
+use super::WaveEquation;
+
+/// Displacement at a probe after one time step
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct ProbeSample {
+    /// number of calls to step
+    pub step: u64,
+    /// simulated time in s
+    pub time: f32,
+    pub displacement: f32,
+}
+
+/// Time series of the displacement at a fixed position, keeps the latest samples up to the capacity
+pub struct Probe {
+    /// position in nodes
+    pub x: f32,
+    pub y: f32,
+    capacity: usize,
+    samples: VecDeque<ProbeSample>,
+}
+
+impl Probe {
+    pub fn new(x: f32, y: f32, capacity: usize) -> Self {
+        Self {
+            x,
+            y,
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Appends the current displacement at the probe, drops the oldest sample if the probe is full
+    /// Probes outside of the grid record nothing
+    pub fn record(&mut self, wave_equation: &WaveEquation) {
+        let displacement = match wave_equation.displacement_at(self.x, self.y) {
+            Some(displacement) => displacement,
+            None => return,
+        };
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ProbeSample {
+            step: wave_equation.steps(),
+            time: wave_equation.time(),
+            displacement,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Iterates from the oldest to the latest sample
+    pub fn samples(&self) -> impl Iterator<Item = &ProbeSample> {
+        self.samples.iter()
+    }
+
+    /// Displacements from the oldest to the latest sample
+    pub fn series(&self) -> Vec<f32> {
+        self.samples.iter().map(|sample| sample.displacement).collect()
+    }
+}
+
+/// Writes the samples of all probes as csv, one sample per line
+pub fn write_probes_csv(probes: &[Probe], mut writer: impl std::io::Write) -> std::io::Result<()> {
+    writeln!(writer, "probe,x,y,step,time,displacement")?;
+    for (index, probe) in probes.iter().enumerate() {
+        for sample in probe.samples() {
+            writeln!(writer, "{},{},{},{},{},{}", index, probe.x, probe.y, sample.step, sample.time, sample.displacement)?;
+        }
+    }
+    writer.flush()
+}
+
+impl WaveEquation {
+    /// Displacement at a position in nodes, interpolated bilinearly between the surrounding nodes
+    /// Returns None outside of the grid
+    pub fn displacement_at(&self, x: f32, y: f32) -> Option<f32> {
+        if !(x >= 0.0 && x <= (self.width-1) as f32 && y >= 0.0 && y <= (self.height-1) as f32) {
+            return None;
+        }
+        let x_i = (x.floor() as usize).min(self.width-2);
+        let y_i = (y.floor() as usize).min(self.height-2);
+        let w_x = x - x_i as f32;
+        let w_y = y - y_i as f32;
+
+        let u = |x, y| self.current[self.index(x, y)];
+        Some(u(x_i, y_i) * (1.0-w_x) * (1.0-w_y)
+            + u(x_i+1, y_i) * w_x * (1.0-w_y)
+            + u(x_i, y_i+1) * (1.0-w_x) * w_y
+            + u(x_i+1, y_i+1) * w_x * w_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::PointSource;
+
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 30;
+
+    #[test]
+    fn displacement_is_interpolated() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                wave_equation.current[y * WIDTH + x] = x as f32 + 100.0 * y as f32;
+            }
+        }
+        assert_eq!(wave_equation.displacement_at(3.0, 2.0), Some(203.0));
+        assert_eq!(wave_equation.displacement_at(3.5, 2.25), Some(228.5));
+        assert_eq!(wave_equation.displacement_at((WIDTH-1) as f32, (HEIGHT-1) as f32), Some(2939.0));
+        assert_eq!(wave_equation.displacement_at(-0.5, 2.0), None);
+        assert_eq!(wave_equation.displacement_at(3.0, HEIGHT as f32), None);
+    }
+
+    #[test]
+    fn probe_keeps_latest_samples() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.add_source(PointSource::new(20.5, 15.5, 1.0, 4.0));
+        let mut probe = Probe::new(25.0, 15.0, 8);
+        let mut outside = Probe::new(-1.0, 15.0, 8);
+        for _i in 0..20 {
+            wave_equation.step(None);
+            probe.record(&wave_equation);
+            outside.record(&wave_equation);
+        }
+
+        assert_eq!(probe.len(), 8);
+        assert!(outside.is_empty());
+        assert_eq!(probe.samples().map(|sample| sample.step).collect::<Vec<_>>(), (13..=20).collect::<Vec<_>>());
+        let latest = probe.samples().last().unwrap();
+        assert_eq!(latest.displacement, wave_equation.get_current()[15 * WIDTH + 25]);
+        assert_eq!(latest.time, wave_equation.time());
+        assert!(probe.series().iter().any(|u| *u != 0.0));
+    }
+
+    #[test]
+    fn probes_export_csv() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let mut probes = [Probe::new(10.0, 10.0, 4), Probe::new(20.5, 12.0, 4)];
+        for _i in 0..2 {
+            wave_equation.step(None);
+            probes.iter_mut().for_each(|probe| probe.record(&wave_equation));
+        }
+
+        let mut csv = Vec::new();
+        write_probes_csv(&probes, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "probe,x,y,step,time,displacement");
+        assert_eq!(lines[1], "0,10,10,1,0.05,0");
+        assert_eq!(lines[4], "1,20.5,12,2,0.1,0");
+    }
+}
@@ -0,0 +1,316 @@
+//! Recording of the user input, which can be replayed to reproduce a run bit for bit
+//!
+//! A recording starts with a snapshot of the simulation and lists every call to add_impulse, interupt_mouse,
+//! step and reset afterwards. Other changes, e.g. adding sources or changing parameters, are not recorded.
+//! Setting initial conditions or a preset ends the recorded input, the recording then reproduces the run up to that change.
+//!
+
+use super::snapshot::Decoder;
+use super::snapshot::Encoder;
+use super::snapshot::read_container;
+use super::snapshot::write_container;
+use super::SnapshotError;
+use super::WaveEquation;
+
+const MAGIC: &[u8; 8] = b"WAVEREPL";
+/// Version of the replay format written by InputRecording::save
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Call to the wave equation which changes its state
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum InputEvent {
+    /// add_impulse with the position in nodes
+    Impulse { y: f32, x: f32 },
+    /// interupt_mouse
+    InterruptMouse,
+    /// step with the number of substeps
+    Step { substeps: Option<usize> },
+    /// reset
+    Reset,
+}
+
+/// Input event with the number of steps performed before it
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct RecordedInput {
+    pub step: u64,
+    pub event: InputEvent,
+}
+
+/// Initial state and input of a recorded run
+#[derive(Clone)]
+pub struct InputRecording {
+    snapshot: Vec<u8>,
+    inputs: Vec<RecordedInput>,
+    // no more input is recorded after a change which can not be replayed
+    closed: bool,
+}
+
+impl InputRecording {
+    fn new(wave_equation: &WaveEquation) -> Self {
+        Self {
+            snapshot: wave_equation.to_snapshot_bytes(),
+            inputs: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Iterates over the recorded inputs in the order of the calls
+    pub fn inputs(&self) -> impl Iterator<Item = &RecordedInput> {
+        self.inputs.iter()
+    }
+
+    /// Restores the simulation at the start of the recording
+    pub fn start(&self) -> Result<WaveEquation, SnapshotError> {
+        WaveEquation::from_snapshot_bytes(&self.snapshot)
+    }
+
+    /// Replays the whole recording on the restored initial state
+    pub fn replay(&self) -> Result<WaveEquation, SnapshotError> {
+        let mut replay = Replay::new(self.clone());
+        let mut wave_equation = replay.recording().start()?;
+        while replay.step(&mut wave_equation) {}
+        Ok(wave_equation)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+        e.u64(self.snapshot.len() as u64);
+        e.bytes.extend_from_slice(&self.snapshot);
+        e.u64(self.inputs.len() as u64);
+        for input in &self.inputs {
+            e.u64(input.step);
+            match input.event {
+                InputEvent::Impulse { y, x } => {
+                    e.u8(0);
+                    e.f32(y);
+                    e.f32(x);
+                },
+                InputEvent::InterruptMouse => e.u8(1),
+                InputEvent::Step { substeps } => {
+                    e.u8(2);
+                    e.u64(substeps.map_or(u64::MAX, |substeps| substeps as u64));
+                },
+                InputEvent::Reset => e.u8(3),
+            }
+        }
+        write_container(MAGIC, REPLAY_VERSION, &e.bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut d = Decoder { bytes: read_container(bytes, MAGIC, REPLAY_VERSION)? };
+        let len = d.u64()?;
+        if (d.bytes.len() as u64) < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (snapshot, rest) = d.bytes.split_at(len as usize);
+        d.bytes = rest;
+
+        let mut inputs = Vec::new();
+        for _i in 0..d.u64()? {
+            let step = d.u64()?;
+            let event = match d.u8()? {
+                0 => InputEvent::Impulse { y: d.f32()?, x: d.f32()? },
+                1 => InputEvent::InterruptMouse,
+                2 => {
+                    let substeps = d.u64()?;
+                    InputEvent::Step { substeps: if substeps == u64::MAX { None } else { Some(substeps as usize) } }
+                },
+                3 => InputEvent::Reset,
+                _ => return Err(SnapshotError::InvalidValue("input event")),
+            };
+            inputs.push(RecordedInput { step, event });
+        }
+        d.finish()?;
+
+        Ok(Self {
+            snapshot: snapshot.to_vec(),
+            inputs,
+            closed: false,
+        })
+    }
+
+    /// Writes the recording, e.g. to a file
+    pub fn save(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        writer.flush()
+    }
+
+    /// Reads a recording written by save
+    pub fn load(mut reader: impl std::io::Read) -> Result<Self, SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Feeds a recording step by step into a wave equation, e.g. once per frame
+pub struct Replay {
+    recording: InputRecording,
+    position: usize,
+}
+
+impl Replay {
+    pub fn new(recording: InputRecording) -> Self {
+        Self {
+            recording,
+            position: 0,
+        }
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    /// Applies the recorded inputs up to and including the next step, returns false at the end of the recording
+    pub fn step(&mut self, wave_equation: &mut WaveEquation) -> bool {
+        while let Some(input) = self.recording.inputs.get(self.position) {
+            self.position += 1;
+            match input.event {
+                InputEvent::Impulse { y, x } => wave_equation.add_impulse(y, x),
+                InputEvent::InterruptMouse => wave_equation.interupt_mouse(),
+                InputEvent::Reset => wave_equation.reset(),
+                InputEvent::Step { substeps } => {
+                    wave_equation.step(substeps);
+                    return true;
+                },
+            }
+        }
+        false
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position == self.recording.inputs.len()
+    }
+}
+
+impl WaveEquation {
+    /// Starts recording the input from the current state on, restarts a running recording
+    pub fn start_recording(&mut self) {
+        self.recording = None;
+        self.recording = Some(InputRecording::new(self));
+    }
+
+    /// Stops recording and returns the recording
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub(super) fn record(&mut self, event: InputEvent) {
+        if let Some(recording) = self.recording.as_mut().filter(|recording| !recording.closed) {
+            recording.inputs.push(RecordedInput { step: self.steps, event });
+        }
+    }
+
+    // Ends the recorded input before a change which is not recorded, the recording stays until stop_recording
+    pub(super) fn close_recording(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.closed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::InitialCondition;
+
+    const WIDTH: usize = 60;
+    const HEIGHT: usize = 50;
+
+    // Mouse strokes which cross cells in all directions, partly on integer coordinates
+    fn play(wave_equation: &mut WaveEquation) {
+        for i in 0..40 {
+            let angle = i as f32 * 0.3;
+            wave_equation.add_impulse(25.0 + 8.0 * angle.sin(), 30.0 + 1.7 * i as f32 % 20.0 * angle.cos());
+            if i % 7 == 0 {
+                wave_equation.add_impulse(20.0, 10.0 + (i / 7) as f32);
+                wave_equation.interupt_mouse();
+            }
+            wave_equation.step(Some(1 + i % 2));
+        }
+        wave_equation.step(None);
+    }
+
+    #[test]
+    fn replay_is_bit_identical() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.add_impulse(10.5, 10.5);
+        wave_equation.step(None);
+        wave_equation.start_recording();
+        play(&mut wave_equation);
+        let recording = wave_equation.stop_recording().unwrap();
+        assert!(!wave_equation.is_recording());
+        assert_eq!(recording.inputs().filter(|input| matches!(input.event, InputEvent::Step { .. })).count(), 41);
+        assert_eq!(recording.inputs().next().unwrap().step, 1);
+
+        let replayed = recording.replay().unwrap();
+        assert!(wave_equation.divergence().is_none());
+        assert_eq!(replayed.steps(), wave_equation.steps());
+        assert_eq!(replayed.field_checksum(), wave_equation.field_checksum());
+        assert_eq!(replayed.get_current(), wave_equation.get_current());
+
+        // later input is not part of the recording
+        wave_equation.add_impulse(10.5, 10.5);
+        wave_equation.step(None);
+        assert_ne!(recording.replay().unwrap().field_checksum(), wave_equation.field_checksum());
+    }
+
+    #[test]
+    fn recording_ends_at_initial_conditions() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.start_recording();
+        play(&mut wave_equation);
+        let checksum = wave_equation.field_checksum();
+        let steps = wave_equation.steps();
+
+        wave_equation.set_initial_condition(InitialCondition::GaussianBump { x: 30.0, y: 25.0, width: 3.0, amplitude: 1.0 });
+        play(&mut wave_equation);
+        assert!(wave_equation.is_recording());
+        let recording = wave_equation.stop_recording().unwrap();
+        assert!(recording.inputs().all(|input| input.event != InputEvent::Reset));
+
+        // the replay reproduces the run up to the initial conditions, instead of zeroing the field
+        let replayed = recording.replay().unwrap();
+        assert_eq!(replayed.steps(), steps);
+        assert_eq!(replayed.field_checksum(), checksum);
+    }
+
+    #[test]
+    fn recording_file_round_trip() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.start_recording();
+        play(&mut wave_equation);
+        wave_equation.reset();
+        wave_equation.add_impulse(30.5, 30.5);
+        wave_equation.step(None);
+        let recording = wave_equation.stop_recording().unwrap();
+
+        let mut file = Vec::new();
+        recording.save(&mut file).unwrap();
+        let loaded = InputRecording::load(file.as_slice()).unwrap();
+        assert_eq!(loaded.inputs().collect::<Vec<_>>(), recording.inputs().collect::<Vec<_>>());
+        assert_eq!(loaded.replay().unwrap().field_checksum(), wave_equation.field_checksum());
+
+        // frame by frame
+        let mut replayed = loaded.start().unwrap();
+        let mut replay = Replay::new(loaded);
+        let mut frames = 0;
+        while replay.step(&mut replayed) {
+            frames += 1;
+        }
+        assert!(replay.is_finished());
+        assert_eq!(frames, 42);
+        assert_eq!(replayed.field_checksum(), wave_equation.field_checksum());
+
+        file[40] ^= 1;
+        assert!(matches!(InputRecording::load(file.as_slice()), Err(SnapshotError::ChecksumMismatch)));
+    }
+}
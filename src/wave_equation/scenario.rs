@@ -0,0 +1,829 @@
+//! Declarative description of an experiment, which is loaded from a toml file
+//!
+//! ```toml
+//! [grid]
+//! width = 280
+//! height = 320
+//! damping = 0.0
+//!
+//! [boundaries]
+//! all = "absorbing"
+//!
+//! [[obstacles]]
+//! shape = { type = "rectangle", x_0 = 0, y_0 = 200, x_1 = 279, y_1 = 202 }
+//!
+//! [[point_sources]]
+//! x = 140
+//! y = 100
+//! frequency = 1.0
+//! amplitude = 4.0
+//!
+//! [[probes]]
+//! x = 140
+//! y = 280
+//! ```
+//!
+//! All sections are optional, missing grid parameters take the defaults of the app.
+//! Positions and lengths are measured in nodes, scenarios/example.toml shows all keys.
+//!
+
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::Boundaries;
+use super::BoundaryCondition;
+use super::Edge;
+use super::Envelope;
+use super::InitialCondition;
+use super::Integrator;
+use super::Medium;
+use super::MovingSource;
+use super::Obstacle;
+use super::PlaneWaveSource;
+use super::PointSource;
+use super::Probe;
+use super::ScenarioError;
+use super::Shape;
+use super::SourcePath;
+use super::SpongeLayer;
+use super::Stencil;
+use super::WaveEquation;
+use super::WaveEquationConfig;
+use super::WaveEquationError;
+use super::Waveform;
+use super::displacement_from_image;
+
+// Layout of the file, the values are checked while the wave equation is built
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    grid: Option<Spanned<GridSection>>,
+    boundaries: Option<Spanned<BoundariesSection>>,
+    sponge: Option<Spanned<SpongeSection>>,
+    medium: Option<Spanned<MediumSection>>,
+    #[serde(default)]
+    obstacles: Vec<ObstacleEntry>,
+    #[serde(default)]
+    initial_conditions: Vec<Spanned<InitialConditionEntry>>,
+    #[serde(default)]
+    point_sources: Vec<Spanned<PointSourceEntry>>,
+    #[serde(default)]
+    plane_waves: Vec<Spanned<PlaneWaveEntry>>,
+    #[serde(default)]
+    moving_sources: Vec<Spanned<MovingSourceEntry>>,
+    #[serde(default)]
+    probes: Vec<Spanned<ProbeEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GridSection {
+    width: Option<Spanned<usize>>,
+    height: Option<Spanned<usize>>,
+    h: Option<Spanned<f32>>,
+    delta_t: Option<Spanned<f32>>,
+    wave_speed: Option<Spanned<f32>>,
+    damping: Option<Spanned<f32>>,
+    stencil: Option<StencilName>,
+    integrator: Option<IntegratorName>,
+    max_amplitude: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoundariesSection {
+    /// default of the edges which are not listed
+    all: Option<ConditionName>,
+    left: Option<ConditionName>,
+    right: Option<ConditionName>,
+    bottom: Option<ConditionName>,
+    top: Option<ConditionName>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpongeSection {
+    thickness: usize,
+    strength: Spanned<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumSection {
+    /// grayscale image of the wave speed between min_speed and max_speed
+    image: Option<Spanned<String>>,
+    min_speed: Option<Spanned<f32>>,
+    max_speed: Option<Spanned<f32>>,
+    #[serde(default)]
+    regions: Vec<Spanned<RegionEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionEntry {
+    shape: Spanned<ShapeDesc>,
+    wave_speed: Option<Spanned<f32>>,
+    /// wave speed relative to the wave speed of the grid
+    refractive_index: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObstacleEntry {
+    shape: Spanned<ShapeDesc>,
+    #[serde(default)]
+    kind: ObstacleKind,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum InitialConditionEntry {
+    GaussianBump { x: f32, y: f32, width: f32, amplitude: f32 },
+    Ring { x: f32, y: f32, radius: f32, width: f32, amplitude: f32 },
+    PlaneWavePacket { x: f32, y: f32, direction: f32, waveform: WaveformDesc, amplitude: f32 },
+    /// grayscale image of the displacement between 0 and amplitude
+    Image { path: String, amplitude: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PointSourceEntry {
+    x: f32,
+    y: f32,
+    amplitude: Spanned<f32>,
+    frequency: Option<Spanned<f32>>,
+    waveform: Option<Spanned<WaveformDesc>>,
+    envelope: Option<Spanned<EnvelopeDesc>>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneWaveEntry {
+    edge: EdgeName,
+    amplitude: Spanned<f32>,
+    frequency: Option<Spanned<f32>>,
+    waveform: Option<Spanned<WaveformDesc>>,
+    envelope: Option<Spanned<EnvelopeDesc>>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MovingSourceEntry {
+    path: Spanned<PathDesc>,
+    speed: Spanned<f32>,
+    amplitude: Spanned<f32>,
+    frequency: Option<Spanned<f32>>,
+    waveform: Option<Spanned<WaveformDesc>>,
+    envelope: Option<Spanned<EnvelopeDesc>>,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeEntry {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvelopeDesc {
+    start: Option<f32>,
+    stop: Option<f32>,
+    ramp: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum ShapeDesc {
+    Rectangle { x_0: f32, y_0: f32, x_1: f32, y_1: f32 },
+    Circle { x: f32, y: f32, radius: f32 },
+    Line { x_0: f32, y_0: f32, x_1: f32, y_1: f32, thickness: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum PathDesc {
+    Line { x_0: f32, y_0: f32, x_1: f32, y_1: f32 },
+    Circle { x: f32, y: f32, radius: f32 },
+    Polyline { points: Vec<[f32; 2]> },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum WaveformDesc {
+    Sine { frequency: f32, #[serde(default)] phase: f32 },
+    GaussianPulse { delay: f32, width: f32 },
+    Ricker { frequency: f32, delay: f32 },
+    Chirp { start_frequency: f32, end_frequency: f32, duration: f32 },
+    SquareBurst { frequency: f32, cycles: u32, delay: f32 },
+    WhiteNoise { seed: u64, sample_rate: f32 },
+}
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum StencilName {
+    FivePoint,
+    NinePoint,
+    FourthOrder,
+}
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum IntegratorName {
+    Verlet,
+    Leapfrog,
+    #[serde(rename = "rk4")]
+    RungeKutta4,
+    CrankNicolson,
+}
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum ConditionName {
+    Fixed,
+    Free,
+    Periodic,
+    Absorbing,
+}
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+enum EdgeName {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+#[derive(Deserialize)]
+#[derive(Copy, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+enum ObstacleKind {
+    #[default]
+    Fixed,
+    Free,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl From<StencilName> for Stencil {
+    fn from(name: StencilName) -> Self {
+        match name {
+            StencilName::FivePoint => Stencil::FivePoint,
+            StencilName::NinePoint => Stencil::NinePoint,
+            StencilName::FourthOrder => Stencil::FourthOrder,
+        }
+    }
+}
+
+impl From<IntegratorName> for Integrator {
+    fn from(name: IntegratorName) -> Self {
+        match name {
+            IntegratorName::Verlet => Integrator::Verlet,
+            IntegratorName::Leapfrog => Integrator::Leapfrog,
+            IntegratorName::RungeKutta4 => Integrator::RungeKutta4,
+            IntegratorName::CrankNicolson => Integrator::CrankNicolson,
+        }
+    }
+}
+
+impl From<ConditionName> for BoundaryCondition {
+    fn from(name: ConditionName) -> Self {
+        match name {
+            ConditionName::Fixed => BoundaryCondition::Fixed,
+            ConditionName::Free => BoundaryCondition::Free,
+            ConditionName::Periodic => BoundaryCondition::Periodic,
+            ConditionName::Absorbing => BoundaryCondition::Absorbing,
+        }
+    }
+}
+
+impl From<EdgeName> for Edge {
+    fn from(name: EdgeName) -> Self {
+        match name {
+            EdgeName::Left => Edge::Left,
+            EdgeName::Right => Edge::Right,
+            EdgeName::Bottom => Edge::Bottom,
+            EdgeName::Top => Edge::Top,
+        }
+    }
+}
+
+impl From<ObstacleKind> for Obstacle {
+    fn from(kind: ObstacleKind) -> Self {
+        match kind {
+            ObstacleKind::Fixed => Obstacle::Fixed,
+            ObstacleKind::Free => Obstacle::Free,
+        }
+    }
+}
+
+// Checks of single values, the error message describes the valid range
+
+fn finite(value: f32) -> Result<f32, &'static str> {
+    if value.is_finite() { Ok(value) } else { Err("needs to be a finite number") }
+}
+
+fn positive(value: f32) -> Result<f32, &'static str> {
+    if value.is_finite() && value > 0.0 { Ok(value) } else { Err("needs to be a positive number") }
+}
+
+impl ShapeDesc {
+    fn to_shape(&self) -> Result<Shape, &'static str> {
+        match *self {
+            ShapeDesc::Rectangle { x_0, y_0, x_1, y_1 } =>
+                Ok(Shape::Rectangle { x_0: finite(x_0)?, y_0: finite(y_0)?, x_1: finite(x_1)?, y_1: finite(y_1)? }),
+            ShapeDesc::Circle { x, y, radius } => Ok(Shape::Circle { x: finite(x)?, y: finite(y)?, radius: positive(radius)? }),
+            ShapeDesc::Line { x_0, y_0, x_1, y_1, thickness } => Ok(Shape::Line {
+                x_0: finite(x_0)?, y_0: finite(y_0)?, x_1: finite(x_1)?, y_1: finite(y_1)?, thickness: positive(thickness)? }),
+        }
+    }
+}
+
+impl PathDesc {
+    fn to_path(&self) -> Result<SourcePath, &'static str> {
+        match self {
+            PathDesc::Line { x_0, y_0, x_1, y_1 } =>
+                Ok(SourcePath::Line { x_0: finite(*x_0)?, y_0: finite(*y_0)?, x_1: finite(*x_1)?, y_1: finite(*y_1)? }),
+            PathDesc::Circle { x, y, radius } => Ok(SourcePath::Circle { x: finite(*x)?, y: finite(*y)?, radius: positive(*radius)? }),
+            PathDesc::Polyline { points } => {
+                if points.len() < 2 {
+                    return Err("a polyline needs at least 2 points");
+                }
+                points.iter().map(|[x, y]| Ok((finite(*x)?, finite(*y)?))).collect::<Result<_, _>>().map(SourcePath::Polyline)
+            },
+        }
+    }
+}
+
+impl WaveformDesc {
+    fn to_waveform(&self) -> Result<Waveform, &'static str> {
+        match *self {
+            WaveformDesc::Sine { frequency, phase } => Ok(Waveform::Sine { frequency: positive(frequency)?, phase: finite(phase)? }),
+            WaveformDesc::GaussianPulse { delay, width } => Ok(Waveform::GaussianPulse { delay: finite(delay)?, width: positive(width)? }),
+            WaveformDesc::Ricker { frequency, delay } => Ok(Waveform::Ricker { frequency: positive(frequency)?, delay: finite(delay)? }),
+            WaveformDesc::Chirp { start_frequency, end_frequency, duration } => Ok(Waveform::Chirp {
+                start_frequency: positive(start_frequency)?, end_frequency: positive(end_frequency)?, duration: positive(duration)? }),
+            WaveformDesc::SquareBurst { frequency, cycles, delay } =>
+                Ok(Waveform::SquareBurst { frequency: positive(frequency)?, cycles, delay: finite(delay)? }),
+            WaveformDesc::WhiteNoise { seed, sample_rate } => Ok(Waveform::WhiteNoise { seed, sample_rate: positive(sample_rate)? }),
+        }
+    }
+}
+
+impl EnvelopeDesc {
+    fn to_envelope(&self) -> Result<Envelope, &'static str> {
+        let default = Envelope::default();
+        let envelope = Envelope {
+            start: finite(self.start.unwrap_or(default.start))?,
+            stop: self.stop.unwrap_or(default.stop),
+            ramp: self.ramp.unwrap_or(default.ramp),
+        };
+        if envelope.stop.is_nan() || envelope.stop < envelope.start {
+            return Err("stop needs to be after start");
+        }
+        if !envelope.ramp.is_finite() || envelope.ramp < 0.0 {
+            return Err("ramp needs to be a finite number of at least 0");
+        }
+        Ok(envelope)
+    }
+}
+
+// Line and column of a byte offset, both start at 1
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+/// An experiment loaded from a toml file, which builds a ready wave equation
+pub struct Scenario {
+    text: String,
+    file: ScenarioFile,
+    /// images are loaded relative to this directory
+    directory: PathBuf,
+}
+
+impl Scenario {
+    /// Parses a scenario, images are loaded relative to the working directory
+    pub fn from_toml(text: &str) -> Result<Self, ScenarioError> {
+        Self::parse(text, PathBuf::new())
+    }
+
+    /// Reads a scenario file, images are loaded relative to the directory of the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, path.parent().map(Path::to_path_buf).unwrap_or_default())
+    }
+
+    fn parse(text: &str, directory: PathBuf) -> Result<Self, ScenarioError> {
+        let file = toml::from_str(text).map_err(|err| {
+            let (line, column) = position(text, err.span().map_or(0, |span| span.start));
+            ScenarioError::Syntax { line, column, message: err.message().to_string() }
+        })?;
+
+        Ok(Self {
+            text: text.to_string(),
+            file,
+            directory,
+        })
+    }
+
+    /// Grid size and parameters of the scenario, missing values take the defaults of the app
+    pub fn config(&self) -> WaveEquationConfig {
+        let mut config = WaveEquationConfig::new(280, 320);
+        if let Some(grid) = &self.file.grid {
+            let grid = grid.get_ref();
+            let value = |value: &Option<Spanned<f32>>, default: f32| value.as_ref().map_or(default, |value| *value.get_ref());
+            config.width = grid.width.as_ref().map_or(config.width, |width| *width.get_ref());
+            config.height = grid.height.as_ref().map_or(config.height, |height| *height.get_ref());
+            config.h = value(&grid.h, config.h);
+            config.delta_t = value(&grid.delta_t, config.delta_t);
+            config.wave_speed = value(&grid.wave_speed, config.wave_speed);
+            config.damping = value(&grid.damping, config.damping);
+            config.stencil = grid.stencil.map_or(config.stencil, Into::into);
+            config.integrator = grid.integrator.map_or(config.integrator, Into::into);
+            config.max_amplitude = value(&grid.max_amplitude, config.max_amplitude);
+        }
+        config
+    }
+
+    /// Builds the wave equation described by the scenario, fails at the first invalid value
+    pub fn build(&self) -> Result<WaveEquation, ScenarioError> {
+        self.build_with_config(self.config())
+    }
+
+    /// Builds the scenario with changed grid parameters, e.g. from the command line
+    pub fn build_with_config(&self, config: WaveEquationConfig) -> Result<WaveEquation, ScenarioError> {
+        let mut wave_equation = match WaveEquation::with_config(config) {
+            Ok(wave_equation) => wave_equation,
+            Err(err) if config == self.config() => return Err(self.grid_error(err)),
+            Err(err) => return Err(ScenarioError::Parameters(err)),
+        };
+
+        self.build_boundaries(&mut wave_equation)?;
+        self.build_medium(&mut wave_equation, config.wave_speed)?;
+        for (i, obstacle) in self.file.obstacles.iter().enumerate() {
+            let shape = obstacle.shape.get_ref().to_shape()
+                .map_err(|message| self.invalid(obstacle.shape.span(), format!("obstacles[{i}].shape"), message))?;
+            wave_equation.obstacles_mut().stamp(shape, Some(obstacle.kind.into()));
+        }
+        self.build_initial_conditions(&mut wave_equation)?;
+        self.build_sources(&mut wave_equation)?;
+        for (i, probe) in self.file.probes.iter().enumerate() {
+            let ProbeEntry { x, y } = *probe.get_ref();
+            self.check_position(&wave_equation, probe.span(), format!("probes[{i}]"), x, y)?;
+        }
+
+        Ok(wave_equation)
+    }
+
+    /// Probes of the scenario, which keep the latest capacity samples
+    pub fn probes(&self, capacity: usize) -> Vec<Probe> {
+        self.file.probes.iter()
+            .map(|probe| Probe::new(probe.get_ref().x, probe.get_ref().y, capacity))
+            .collect()
+    }
+
+    fn invalid(&self, span: Range<usize>, field: impl Into<String>, message: impl Into<String>) -> ScenarioError {
+        let (line, column) = position(&self.text, span.start);
+        ScenarioError::InvalidValue { line, column, field: field.into(), message: message.into() }
+    }
+
+    // Points to the grid parameter which could not be used
+    fn grid_error(&self, err: WaveEquationError) -> ScenarioError {
+        let grid = self.file.grid.as_ref();
+        let span = |name: &str| grid.map(|grid| {
+            let section = grid.get_ref();
+            let span = match name {
+                "width" => section.width.as_ref().map(Spanned::span),
+                "height" => section.height.as_ref().map(Spanned::span),
+                "h" => section.h.as_ref().map(Spanned::span),
+                "delta_t" => section.delta_t.as_ref().map(Spanned::span),
+                "wave_speed" => section.wave_speed.as_ref().map(Spanned::span),
+                "damping" => section.damping.as_ref().map(Spanned::span),
+                "max_amplitude" => section.max_amplitude.as_ref().map(Spanned::span),
+                _ => None,
+            };
+            span.unwrap_or(grid.span())
+        }).unwrap_or(0..0);
+
+        let (name, message) = match err {
+            WaveEquationError::InvalidGridSize { width, .. } =>
+                (if width < 3 { "width" } else { "height" }, "needs to be at least 3".to_string()),
+            WaveEquationError::InvalidParameter { name: "damping", .. } =>
+                ("damping", "needs to be a finite number of at least 0".to_string()),
+            WaveEquationError::InvalidParameter { name, .. } => (name, "needs to be a positive number".to_string()),
+            WaveEquationError::Unstable { courant_number, limit } =>
+                ("delta_t", format!("the courant number c * delta_t / h = {courant_number} exceeds the stability limit {limit}")),
+        };
+        self.invalid(span(name), format!("grid.{name}"), message)
+    }
+
+    fn check_position(&self, wave_equation: &WaveEquation, span: Range<usize>, field: String, x: f32, y: f32) -> Result<(), ScenarioError> {
+        if wave_equation.displacement_at(x, y).is_none() {
+            let message = format!("x = {x}, y = {y} lies outside of the grid of {} x {} nodes", wave_equation.width(), wave_equation.height());
+            return Err(self.invalid(span, field, message));
+        }
+        Ok(())
+    }
+
+    fn read(&self, path: &str, span: Range<usize>, field: &str) -> Result<Vec<u8>, ScenarioError> {
+        std::fs::read(self.directory.join(path))
+            .map_err(|err| self.invalid(span, field, format!("could not read {path}: {err}")))
+    }
+
+    fn build_boundaries(&self, wave_equation: &mut WaveEquation) -> Result<(), ScenarioError> {
+        if let Some(section) = &self.file.boundaries {
+            let names = section.get_ref();
+            let all = names.all.map_or(Boundaries::default().left, Into::into);
+            let boundaries = Boundaries {
+                left: names.left.map_or(all, Into::into),
+                right: names.right.map_or(all, Into::into),
+                bottom: names.bottom.map_or(all, Into::into),
+                top: names.top.map_or(all, Into::into),
+            };
+            let periodic = |condition| condition == BoundaryCondition::Periodic;
+            if periodic(boundaries.left) != periodic(boundaries.right) || periodic(boundaries.bottom) != periodic(boundaries.top) {
+                return Err(self.invalid(section.span(), "boundaries", "periodic needs to be set on both opposite edges"));
+            }
+            wave_equation.set_boundary_conditions(boundaries);
+        }
+
+        if let Some(section) = &self.file.sponge {
+            let sponge = section.get_ref();
+            let strength = *sponge.strength.get_ref();
+            if !strength.is_finite() || strength < 0.0 {
+                return Err(self.invalid(sponge.strength.span(), "sponge.strength", "needs to be a finite number of at least 0"));
+            }
+            wave_equation.set_sponge_layer(SpongeLayer { thickness: sponge.thickness, strength });
+        }
+        Ok(())
+    }
+
+    fn build_medium(&self, wave_equation: &mut WaveEquation, wave_speed: f32) -> Result<(), ScenarioError> {
+        let section = match &self.file.medium {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        let medium_section = section.get_ref();
+        let speed = |value: &Option<Spanned<f32>>, field: &str| match value {
+            Some(value) => positive(*value.get_ref()).map(Some).map_err(|message| self.invalid(value.span(), field, message)),
+            None => Ok(None),
+        };
+        let min_speed = speed(&medium_section.min_speed, "medium.min_speed")?;
+        let max_speed = speed(&medium_section.max_speed, "medium.max_speed")?;
+
+        let (width, height) = (wave_equation.width(), wave_equation.height());
+        let mut medium = match (&medium_section.image, min_speed, max_speed) {
+            (Some(image), Some(min_speed), Some(max_speed)) => {
+                let bytes = self.read(image.get_ref(), image.span(), "medium.image")?;
+                Medium::from_image_bytes(&bytes, width, height, min_speed, max_speed)
+                    .map_err(|err| self.invalid(image.span(), "medium.image", format!("could not decode {}: {err}", image.get_ref())))?
+            },
+            (Some(image), _, _) => return Err(self.invalid(image.span(), "medium.image", "needs min_speed and max_speed")),
+            (None, None, None) => Medium::new(width, height, wave_speed),
+            (None, _, _) => return Err(self.invalid(section.span(), "medium", "min_speed and max_speed are only used with an image")),
+        };
+
+        for (i, region) in medium_section.regions.iter().enumerate() {
+            let field = format!("medium.regions[{i}]");
+            let entry = region.get_ref();
+            let shape = entry.shape.get_ref().to_shape()
+                .map_err(|message| self.invalid(entry.shape.span(), format!("{field}.shape"), message))?;
+            let region_speed = match (&entry.wave_speed, &entry.refractive_index) {
+                (Some(c), None) => positive(*c.get_ref())
+                    .map_err(|message| self.invalid(c.span(), format!("{field}.wave_speed"), message))?,
+                (None, Some(n)) => wave_speed / positive(*n.get_ref())
+                    .map_err(|message| self.invalid(n.span(), format!("{field}.refractive_index"), message))?,
+                _ => return Err(self.invalid(region.span(), field, "needs either a wave_speed or a refractive_index")),
+            };
+            medium.fill(shape, region_speed);
+        }
+
+        wave_equation.set_medium(medium);
+        if let Err(WaveEquationError::Unstable { courant_number, limit }) = wave_equation.check_stability() {
+            let message = format!("the courant number c * delta_t / h = {courant_number} of the fastest node exceeds the stability limit {limit}");
+            return Err(self.invalid(section.span(), "medium", message));
+        }
+        Ok(())
+    }
+
+    fn build_initial_conditions(&self, wave_equation: &mut WaveEquation) -> Result<(), ScenarioError> {
+        if self.file.initial_conditions.is_empty() {
+            return Ok(());
+        }
+
+        // the initial conditions are added up
+        let (width, height) = (wave_equation.width(), wave_equation.height());
+        let mut displacement = vec![0.0; width * height];
+        let mut velocity = vec![0.0; width * height];
+        for (i, entry) in self.file.initial_conditions.iter().enumerate() {
+            let field = format!("initial_conditions[{i}]");
+            let invalid = |message| self.invalid(entry.span(), field.clone(), message);
+            let condition = match *entry.get_ref() {
+                InitialConditionEntry::GaussianBump { x, y, width, amplitude } => InitialCondition::GaussianBump {
+                    x: finite(x).map_err(invalid)?,
+                    y: finite(y).map_err(invalid)?,
+                    width: positive(width).map_err(invalid)?,
+                    amplitude: finite(amplitude).map_err(invalid)?,
+                },
+                InitialConditionEntry::Ring { x, y, radius, width, amplitude } => InitialCondition::Ring {
+                    x: finite(x).map_err(invalid)?,
+                    y: finite(y).map_err(invalid)?,
+                    radius: positive(radius).map_err(invalid)?,
+                    width: positive(width).map_err(invalid)?,
+                    amplitude: finite(amplitude).map_err(invalid)?,
+                },
+                InitialConditionEntry::PlaneWavePacket { x, y, direction, ref waveform, amplitude } => InitialCondition::PlaneWavePacket {
+                    x: finite(x).map_err(invalid)?,
+                    y: finite(y).map_err(invalid)?,
+                    direction: finite(direction).map_err(invalid)?,
+                    waveform: waveform.to_waveform().map_err(invalid)?,
+                    amplitude: finite(amplitude).map_err(invalid)?,
+                },
+                InitialConditionEntry::Image { ref path, amplitude } => {
+                    let amplitude = finite(amplitude).map_err(invalid)?;
+                    let bytes = self.read(path, entry.span(), &format!("{field}.path"))?;
+                    let image = image::load_from_memory(&bytes)
+                        .map_err(|err| self.invalid(entry.span(), format!("{field}.path"), format!("could not decode {path}: {err}")))?;
+                    let image_displacement = displacement_from_image(&image, width, height, amplitude);
+                    displacement.iter_mut().zip(image_displacement).for_each(|(u, image_u)| *u += image_u);
+                    continue;
+                },
+            };
+            for (index, (u, v)) in displacement.iter_mut().zip(velocity.iter_mut()).enumerate() {
+                let x = (index % width) as f32;
+                let y = (index / width) as f32;
+                let c = wave_equation.medium.wave_speed()[index];
+                *u += condition.displacement(x, y, c, wave_equation.h);
+                *v += condition.velocity(x, y, c, wave_equation.h);
+            }
+        }
+
+        wave_equation.set_initial_conditions(&displacement, &velocity);
+        Ok(())
+    }
+
+    // Waveform, envelope and amplitude which all sources have in common
+    fn excitation(&self, span: Range<usize>, field: &str, amplitude: &Spanned<f32>, frequency: &Option<Spanned<f32>>,
+        waveform: &Option<Spanned<WaveformDesc>>, envelope: &Option<Spanned<EnvelopeDesc>>) -> Result<(f32, Waveform, Envelope), ScenarioError> {
+        let amplitude = finite(*amplitude.get_ref())
+            .map_err(|message| self.invalid(amplitude.span(), format!("{field}.amplitude"), message))?;
+        let waveform = match (frequency, waveform) {
+            (Some(frequency), None) => Waveform::sine(positive(*frequency.get_ref())
+                .map_err(|message| self.invalid(frequency.span(), format!("{field}.frequency"), message))?),
+            (None, Some(waveform)) => waveform.get_ref().to_waveform()
+                .map_err(|message| self.invalid(waveform.span(), format!("{field}.waveform"), message))?,
+            _ => return Err(self.invalid(span, field, "needs either a frequency or a waveform")),
+        };
+        let envelope = match envelope {
+            Some(envelope) => envelope.get_ref().to_envelope()
+                .map_err(|message| self.invalid(envelope.span(), format!("{field}.envelope"), message))?,
+            None => Envelope::default(),
+        };
+        Ok((amplitude, waveform, envelope))
+    }
+
+    fn build_sources(&self, wave_equation: &mut WaveEquation) -> Result<(), ScenarioError> {
+        for (i, entry) in self.file.point_sources.iter().enumerate() {
+            let field = format!("point_sources[{i}]");
+            let source = entry.get_ref();
+            self.check_position(wave_equation, entry.span(), field.clone(), source.x, source.y)?;
+            let (amplitude, waveform, envelope) =
+                self.excitation(entry.span(), &field, &source.amplitude, &source.frequency, &source.waveform, &source.envelope)?;
+            wave_equation.add_source(PointSource {
+                envelope,
+                enabled: source.enabled,
+                ..PointSource::with_waveform(source.x, source.y, waveform, amplitude)
+            });
+        }
+
+        for (i, entry) in self.file.plane_waves.iter().enumerate() {
+            let field = format!("plane_waves[{i}]");
+            let source = entry.get_ref();
+            let (amplitude, waveform, envelope) =
+                self.excitation(entry.span(), &field, &source.amplitude, &source.frequency, &source.waveform, &source.envelope)?;
+            wave_equation.add_plane_wave(PlaneWaveSource {
+                envelope,
+                enabled: source.enabled,
+                ..PlaneWaveSource::new(source.edge.into(), waveform, amplitude)
+            });
+        }
+
+        for (i, entry) in self.file.moving_sources.iter().enumerate() {
+            let field = format!("moving_sources[{i}]");
+            let source = entry.get_ref();
+            let path = source.path.get_ref().to_path()
+                .map_err(|message| self.invalid(source.path.span(), format!("{field}.path"), message))?;
+            let speed = *source.speed.get_ref();
+            if !speed.is_finite() || speed < 0.0 {
+                return Err(self.invalid(source.speed.span(), format!("{field}.speed"), "needs to be a finite number of at least 0"));
+            }
+            let (amplitude, waveform, envelope) =
+                self.excitation(entry.span(), &field, &source.amplitude, &source.frequency, &source.waveform, &source.envelope)?;
+            wave_equation.add_moving_source(MovingSource {
+                envelope,
+                enabled: source.enabled,
+                ..MovingSource::with_waveform(path, speed, waveform, amplitude)
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Error position and field of an invalid scenario
+    fn invalid(text: &str) -> (usize, usize, String) {
+        match Scenario::from_toml(text).and_then(|scenario| scenario.build()) {
+            Err(ScenarioError::InvalidValue { line, column, field, .. }) => (line, column, field),
+            Err(err) => panic!("unexpected error {err}"),
+            Ok(_) => panic!("invalid scenario was accepted"),
+        }
+    }
+
+    #[test]
+    fn example_scenario_builds() {
+        let scenario = Scenario::from_toml(include_str!("../../scenarios/example.toml")).unwrap();
+        let wave_equation = scenario.build().unwrap();
+
+        assert_eq!((wave_equation.width(), wave_equation.height()), (280, 320));
+        assert_eq!(wave_equation.boundary_conditions().left, BoundaryCondition::Absorbing);
+        assert_eq!(wave_equation.boundary_conditions().top, BoundaryCondition::Fixed);
+        assert_eq!(wave_equation.sponge_layer(), SpongeLayer { thickness: 10, strength: 2.0 });
+        assert_eq!(wave_equation.medium().get(140, 60), 0.5);
+        assert_eq!(wave_equation.medium().get(10, 10), 1.0);
+        assert_eq!(wave_equation.obstacles().get(10, 200), Some(Obstacle::Fixed));
+        assert_eq!(wave_equation.obstacles().get(140, 200), None);
+        assert_eq!(wave_equation.sources().count(), 2);
+        assert_eq!(wave_equation.plane_waves().count(), 1);
+        assert_eq!(wave_equation.moving_sources().count(), 1);
+        let (_id, source) = wave_equation.sources().nth(1).unwrap();
+        assert_eq!(source.waveform, Waveform::Ricker { frequency: 1.0, delay: 1.5 });
+        assert_eq!(source.envelope.stop, 5.0);
+        assert!(wave_equation.get_current().iter().any(|u| *u != 0.0));
+
+        let probes = scenario.probes(100);
+        assert_eq!(probes.len(), 2);
+        assert_eq!((probes[1].x, probes[1].y, probes[1].capacity()), (140.0, 300.0, 100));
+    }
+
+    #[test]
+    fn errors_point_to_the_value() {
+        let text = "[grid]\nwidth = 40\nheight = 30\n\n[[point_sources]]\nx = 10\ny = 10\nfrequency = -1.0\namplitude = 1.0\n";
+        assert_eq!(invalid(text), (8, 13, "point_sources[0].frequency".to_string()));
+
+        let text = "[grid]\nwidth = 40\nheight = 30\ndelta_t = 1.0\n";
+        assert_eq!(invalid(text), (4, 11, "grid.delta_t".to_string()));
+
+        let text = "[grid]\nwidth = 40\nheight = 30\n\n[[probes]]\nx = 10\ny = 10\n\n[[probes]]\nx = 50\ny = 10\n";
+        assert_eq!(invalid(text), (9, 1, "probes[1]".to_string()));
+
+        let text = "[[plane_waves]]\nedge = \"left\"\namplitude = 1.0\n";
+        assert_eq!(invalid(text), (1, 1, "plane_waves[0]".to_string()));
+
+        let text = "[boundaries]\nleft = \"periodic\"\n";
+        assert_eq!(invalid(text), (1, 1, "boundaries".to_string()));
+    }
+
+    #[test]
+    fn syntax_errors_have_a_position() {
+        let syntax = |text| match Scenario::from_toml(text) {
+            Err(ScenarioError::Syntax { line, column, message }) => (line, column, message),
+            _ => panic!("syntax error was not detected"),
+        };
+
+        let (line, column, _message) = syntax("[grid]\nwidth = 40\nheight = = 30\n");
+        assert_eq!((line, column), (3, 10));
+
+        let (line, _column, message) = syntax("[grid]\nwidth = 40\n\n[[probes]]\nx = 1\nz = 2\n");
+        assert_eq!(line, 6);
+        assert!(message.contains("unknown field `z`"), "{message}");
+
+        let (line, _column, message) = syntax("[grid]\nstencil = \"seven-point\"\n");
+        assert_eq!(line, 2);
+        assert!(message.contains("five-point"), "{message}");
+    }
+}
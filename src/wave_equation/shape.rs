@@ -0,0 +1,53 @@
+//! Simple shapes in grid coordinates, used to paint regions of the grid
+//!
+
+/// A region of the grid, coordinates are measured in nodes
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Shape {
+    /// Axis aligned rectangle between two corners, including the border
+    Rectangle { x_0: f32, y_0: f32, x_1: f32, y_1: f32 },
+    /// Disk around a center, including the border
+    Circle { x: f32, y: f32, radius: f32 },
+    /// Line segment between two points, covering all nodes closer than thickness / 2
+    Line { x_0: f32, y_0: f32, x_1: f32, y_1: f32, thickness: f32 },
+}
+
+impl Shape {
+    /// Checks if the node at x and y lies within the shape
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match *self {
+            Shape::Rectangle { x_0, y_0, x_1, y_1 } => {
+                x >= x_0.min(x_1) && x <= x_0.max(x_1) &&
+                y >= y_0.min(y_1) && y <= y_0.max(y_1)
+            },
+            Shape::Circle { x: x_c, y: y_c, radius } => {
+                (x - x_c) * (x - x_c) + (y - y_c) * (y - y_c) <= radius * radius
+            },
+            Shape::Line { x_0, y_0, x_1, y_1, thickness } => {
+                // closest point on the segment
+                let d_x = x_1 - x_0;
+                let d_y = y_1 - y_0;
+                let length2 = d_x * d_x + d_y * d_y;
+                let t = if length2 > 0.0 { (((x - x_0) * d_x + (y - y_0) * d_y) / length2).clamp(0.0, 1.0) } else { 0.0 };
+                let p_x = x_0 + t * d_x;
+                let p_y = y_0 + t * d_y;
+
+                let half = 0.5 * thickness;
+                (x - p_x) * (x - p_x) + (y - p_y) * (y - p_y) <= half * half
+            },
+        }
+    }
+
+    /// Calls f for every node of a width * height grid within the shape
+    pub fn for_each_node(&self, width: usize, height: usize, mut f: impl FnMut(usize, usize)) {
+        for y in 0..height {
+            for x in 0..width {
+                if self.contains(x as f32, y as f32) {
+                    f(x, y);
+                }
+            }
+        }
+    }
+}
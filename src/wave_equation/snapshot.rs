@@ -0,0 +1,572 @@
+//! Snapshots of the complete simulation state in a versioned binary format
+//!
+//! A snapshot starts with a header of the magic bytes WAVESNAP, the format version (u32),
+//! the length of the payload (u64) and the FNV-1a hash of the payload (u64).
+//! The payload holds the parameters, all fields, the sources and the step counter.
+//! All numbers are stored little endian. The number of worker threads is not part of the state.
+//!
+
+use super::BoundaryCondition;
+use super::Divergence;
+use super::Edge;
+use super::Envelope;
+use super::Integrator;
+use super::Kernel;
+use super::MovingSource;
+use super::Obstacle;
+use super::PlaneWaveSource;
+use super::PointSource;
+use super::SnapshotError;
+use super::SourceId;
+use super::SourcePath;
+use super::SpongeLayer;
+use super::Stencil;
+use super::WaveEquation;
+use super::Waveform;
+
+const MAGIC: &[u8; 8] = b"WAVESNAP";
+/// Version of the snapshot format written by save_snapshot
+pub const SNAPSHOT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + 4 + 8 + 8;
+
+impl WaveEquation {
+    /// Serializes the complete state of the simulation
+    pub fn to_snapshot_bytes(&self) -> Vec<u8> {
+        let mut payload = Encoder::default();
+        self.encode(&mut payload);
+        write_container(MAGIC, SNAPSHOT_VERSION, &payload.bytes)
+    }
+
+    /// Restores a simulation from the bytes of to_snapshot_bytes, which continues exactly like the saved one
+    pub fn from_snapshot_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let payload = read_container(bytes, MAGIC, SNAPSHOT_VERSION)?;
+        let mut decoder = Decoder { bytes: payload };
+        let wave_equation = Self::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(wave_equation)
+    }
+
+    /// FNV-1a hash of the current displacement, to check whether two runs are identical bit for bit
+    pub fn field_checksum(&self) -> u64 {
+        let mut field = Encoder::default();
+        field.f32s(&self.current);
+        fnv1a(&field.bytes)
+    }
+
+    /// Writes a snapshot, e.g. to a file
+    pub fn save_snapshot(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(&self.to_snapshot_bytes())?;
+        writer.flush()
+    }
+
+    /// Reads a snapshot written by save_snapshot
+    pub fn load_snapshot(mut reader: impl std::io::Read) -> Result<Self, SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_snapshot_bytes(&bytes)
+    }
+
+    fn encode(&self, e: &mut Encoder) {
+        // parameters
+        e.u64(self.width as u64);
+        e.u64(self.height as u64);
+        e.f32(self.h);
+        e.f32(self.delta_t);
+        e.f32(self.substep_delta_t);
+        e.u8(self.stencil as u8);
+        e.u8(self.kernel as u8);
+        e.u8(self.integrator as u8);
+        e.f32(self.max_amplitude);
+        for edge in [Edge::Left, Edge::Right, Edge::Bottom, Edge::Top] {
+            e.u8(self.boundaries.get(edge) as u8);
+        }
+        e.u64(self.sponge_layer.thickness as u64);
+        e.f32(self.sponge_layer.strength);
+
+        // fields
+        e.f32s(&self.previous);
+        e.f32s(&self.current);
+        e.f32s(&self.next);
+        e.f32s(&self.forces);
+        e.f32s(&self.velocity);
+        e.f32s(&self.damping);
+        e.f32s(self.medium.wave_speed());
+        for cell in self.obstacles.cells() {
+            e.u8(match cell {
+                None => 0,
+                Some(Obstacle::Fixed) => 1,
+                Some(Obstacle::Free) => 2,
+            });
+        }
+
+        // sources
+        e.u64(self.next_source_id);
+        e.u64(self.sources.len() as u64);
+        for (id, source) in &self.sources {
+            e.u64(id.0);
+            e.f32(source.x);
+            e.f32(source.y);
+            e.source(source.amplitude, &source.waveform, &source.envelope, source.enabled);
+        }
+        e.u64(self.plane_waves.len() as u64);
+        for (id, source) in &self.plane_waves {
+            e.u64(id.0);
+            e.u8(source.edge as u8);
+            e.source(source.amplitude, &source.waveform, &source.envelope, source.enabled);
+        }
+        e.u64(self.moving_sources.len() as u64);
+        for (id, source) in &self.moving_sources {
+            e.u64(id.0);
+            e.path(&source.path);
+            e.f32(source.speed);
+            e.source(source.amplitude, &source.waveform, &source.envelope, source.enabled);
+        }
+
+        // progress
+        e.u64(self.steps);
+        match self.divergence {
+            Some(divergence) => {
+                e.u8(1);
+                e.u64(divergence.step);
+                e.u64(divergence.substep as u64);
+                e.u64(divergence.x as u64);
+                e.u64(divergence.y as u64);
+                e.f32(divergence.value);
+            },
+            None => e.u8(0),
+        }
+        e.f32(self.x_old);
+        e.f32(self.y_old);
+        e.u8(self.mouse_interupted as u8);
+    }
+
+    fn decode(d: &mut Decoder) -> Result<Self, SnapshotError> {
+        let width = d.u64()? as usize;
+        let height = d.u64()? as usize;
+        // every node needs at least 29 bytes, which rules out sizes before allocating
+        if width < 3 || height < 3 || width.saturating_mul(height) > d.bytes.len() / 29 {
+            return Err(SnapshotError::InvalidValue("grid size"));
+        }
+        let len = width * height;
+        let mut wave_equation = WaveEquation::new(width, height);
+
+        wave_equation.h = d.f32()?;
+        wave_equation.delta_t = d.f32()?;
+        wave_equation.substep_delta_t = d.f32()?;
+        wave_equation.stencil = match d.u8()? {
+            0 => Stencil::FivePoint,
+            1 => Stencil::NinePoint,
+            2 => Stencil::FourthOrder,
+            _ => return Err(SnapshotError::InvalidValue("stencil")),
+        };
+        wave_equation.kernel = match d.u8()? {
+            0 => Kernel::PerNode,
+            1 => Kernel::Interior,
+            _ => return Err(SnapshotError::InvalidValue("kernel")),
+        };
+        wave_equation.integrator = match d.u8()? {
+            0 => Integrator::Verlet,
+            1 => Integrator::Leapfrog,
+            2 => Integrator::RungeKutta4,
+            3 => Integrator::CrankNicolson,
+            _ => return Err(SnapshotError::InvalidValue("integrator")),
+        };
+        wave_equation.max_amplitude = d.f32()?;
+        for edge in [Edge::Left, Edge::Right, Edge::Bottom, Edge::Top] {
+            let condition = match d.u8()? {
+                0 => BoundaryCondition::Fixed,
+                1 => BoundaryCondition::Free,
+                2 => BoundaryCondition::Periodic,
+                3 => BoundaryCondition::Absorbing,
+                _ => return Err(SnapshotError::InvalidValue("boundary condition")),
+            };
+            wave_equation.boundaries.set(edge, condition);
+        }
+        wave_equation.sponge_layer = SpongeLayer {
+            thickness: d.u64()? as usize,
+            strength: d.f32()?,
+        };
+        wave_equation.update_sponge();
+
+        wave_equation.previous = d.f32s(len)?;
+        wave_equation.current = d.f32s(len)?;
+        wave_equation.next = d.f32s(len)?;
+        wave_equation.forces = d.f32s(len)?;
+        wave_equation.velocity = d.f32s(len)?;
+        wave_equation.damping = d.f32s(len)?;
+        let wave_speed = d.f32s(len)?;
+        for (i, c) in wave_speed.into_iter().enumerate() {
+            wave_equation.medium.set(i % width, i / width, c);
+        }
+        for i in 0..len {
+            let obstacle = match d.u8()? {
+                0 => None,
+                1 => Some(Obstacle::Fixed),
+                2 => Some(Obstacle::Free),
+                _ => return Err(SnapshotError::InvalidValue("obstacle")),
+            };
+            wave_equation.obstacles.set(i % width, i / width, obstacle);
+        }
+
+        wave_equation.next_source_id = d.u64()?;
+        for _i in 0..d.u64()? {
+            let id = SourceId(d.u64()?);
+            let x = d.f32()?;
+            let y = d.f32()?;
+            let (amplitude, waveform, envelope, enabled) = d.source()?;
+            wave_equation.sources.push((id, PointSource { x, y, amplitude, waveform, envelope, enabled }));
+        }
+        for _i in 0..d.u64()? {
+            let id = SourceId(d.u64()?);
+            let edge = d.edge()?;
+            let (amplitude, waveform, envelope, enabled) = d.source()?;
+            wave_equation.plane_waves.push((id, PlaneWaveSource { edge, amplitude, waveform, envelope, enabled }));
+        }
+        for _i in 0..d.u64()? {
+            let id = SourceId(d.u64()?);
+            let path = d.path()?;
+            let speed = d.f32()?;
+            let (amplitude, waveform, envelope, enabled) = d.source()?;
+            wave_equation.moving_sources.push((id, MovingSource { path, speed, amplitude, waveform, envelope, enabled }));
+        }
+
+        wave_equation.steps = d.u64()?;
+        wave_equation.divergence = match d.u8()? {
+            0 => None,
+            1 => Some(Divergence {
+                step: d.u64()?,
+                substep: d.u64()? as usize,
+                x: d.u64()? as usize,
+                y: d.u64()? as usize,
+                value: d.f32()?,
+            }),
+            _ => return Err(SnapshotError::InvalidValue("divergence")),
+        };
+        wave_equation.x_old = d.f32()?;
+        wave_equation.y_old = d.f32()?;
+        wave_equation.mouse_interupted = d.u8()? != 0;
+
+        Ok(wave_equation)
+    }
+}
+
+// 64 bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// Prepends the header of magic bytes, version, payload length and checksum
+pub(super) fn write_container(magic: &[u8; 8], version: u32, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(magic);
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&fnv1a(payload).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+// Checks the header written by write_container and returns the payload
+pub(super) fn read_container<'a>(bytes: &'a [u8], magic: &[u8; 8], version: u32) -> Result<&'a [u8], SnapshotError> {
+    if bytes.len() < magic.len() || &bytes[..magic.len()] != magic {
+        return Err(SnapshotError::InvalidMagic);
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(SnapshotError::Truncated);
+    }
+    let mut header = Decoder { bytes: &bytes[magic.len()..HEADER_SIZE] };
+    let file_version = header.u32()?;
+    let len = header.u64()?;
+    let checksum = header.u64()?;
+    if file_version != version {
+        return Err(SnapshotError::UnsupportedVersion(file_version));
+    }
+
+    let payload = &bytes[HEADER_SIZE..];
+    if (payload.len() as u64) < len {
+        return Err(SnapshotError::Truncated);
+    }
+    let payload = &payload[..len as usize];
+    if fnv1a(payload) != checksum {
+        return Err(SnapshotError::ChecksumMismatch);
+    }
+    Ok(payload)
+}
+
+#[derive(Default)]
+pub(super) struct Encoder {
+    pub(super) bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(super) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(super) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn f32s(&mut self, values: &[f32]) {
+        values.iter().for_each(|value| self.f32(*value));
+    }
+
+    fn waveform(&mut self, waveform: &Waveform) {
+        match *waveform {
+            Waveform::Sine { frequency, phase } => {
+                self.u8(0);
+                self.f32(frequency);
+                self.f32(phase);
+            },
+            Waveform::GaussianPulse { delay, width } => {
+                self.u8(1);
+                self.f32(delay);
+                self.f32(width);
+            },
+            Waveform::Ricker { frequency, delay } => {
+                self.u8(2);
+                self.f32(frequency);
+                self.f32(delay);
+            },
+            Waveform::Chirp { start_frequency, end_frequency, duration } => {
+                self.u8(3);
+                self.f32(start_frequency);
+                self.f32(end_frequency);
+                self.f32(duration);
+            },
+            Waveform::SquareBurst { frequency, cycles, delay } => {
+                self.u8(4);
+                self.f32(frequency);
+                self.u32(cycles);
+                self.f32(delay);
+            },
+            Waveform::WhiteNoise { seed, sample_rate } => {
+                self.u8(5);
+                self.u64(seed);
+                self.f32(sample_rate);
+            },
+        }
+    }
+
+    fn path(&mut self, path: &SourcePath) {
+        match path {
+            SourcePath::Line { x_0, y_0, x_1, y_1 } => {
+                self.u8(0);
+                [*x_0, *y_0, *x_1, *y_1].iter().for_each(|value| self.f32(*value));
+            },
+            SourcePath::Circle { x, y, radius } => {
+                self.u8(1);
+                [*x, *y, *radius].iter().for_each(|value| self.f32(*value));
+            },
+            SourcePath::Polyline(points) => {
+                self.u8(2);
+                self.u64(points.len() as u64);
+                for (x, y) in points {
+                    self.f32(*x);
+                    self.f32(*y);
+                }
+            },
+        }
+    }
+
+    // the part all kinds of sources have in common
+    fn source(&mut self, amplitude: f32, waveform: &Waveform, envelope: &Envelope, enabled: bool) {
+        self.f32(amplitude);
+        self.waveform(waveform);
+        self.f32(envelope.start);
+        self.f32(envelope.stop);
+        self.f32(envelope.ramp);
+        self.u8(enabled as u8);
+    }
+}
+
+pub(super) struct Decoder<'a> {
+    pub(super) bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        if self.bytes.len() < N {
+            return Err(SnapshotError::Truncated);
+        }
+        let (value, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(value.try_into().unwrap())
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub(super) fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    pub(super) fn f32s(&mut self, len: usize) -> Result<Vec<f32>, SnapshotError> {
+        if self.bytes.len() / 4 < len {
+            return Err(SnapshotError::Truncated);
+        }
+        (0..len).map(|_| self.f32()).collect()
+    }
+
+    // fails if there are bytes left
+    pub(super) fn finish(&self) -> Result<(), SnapshotError> {
+        if !self.bytes.is_empty() {
+            return Err(SnapshotError::InvalidValue("payload length"));
+        }
+        Ok(())
+    }
+
+    fn edge(&mut self) -> Result<Edge, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(Edge::Left),
+            1 => Ok(Edge::Right),
+            2 => Ok(Edge::Bottom),
+            3 => Ok(Edge::Top),
+            _ => Err(SnapshotError::InvalidValue("edge")),
+        }
+    }
+
+    fn waveform(&mut self) -> Result<Waveform, SnapshotError> {
+        Ok(match self.u8()? {
+            0 => Waveform::Sine { frequency: self.f32()?, phase: self.f32()? },
+            1 => Waveform::GaussianPulse { delay: self.f32()?, width: self.f32()? },
+            2 => Waveform::Ricker { frequency: self.f32()?, delay: self.f32()? },
+            3 => Waveform::Chirp { start_frequency: self.f32()?, end_frequency: self.f32()?, duration: self.f32()? },
+            4 => Waveform::SquareBurst { frequency: self.f32()?, cycles: self.u32()?, delay: self.f32()? },
+            5 => Waveform::WhiteNoise { seed: self.u64()?, sample_rate: self.f32()? },
+            _ => return Err(SnapshotError::InvalidValue("waveform")),
+        })
+    }
+
+    fn path(&mut self) -> Result<SourcePath, SnapshotError> {
+        Ok(match self.u8()? {
+            0 => SourcePath::Line { x_0: self.f32()?, y_0: self.f32()?, x_1: self.f32()?, y_1: self.f32()? },
+            1 => SourcePath::Circle { x: self.f32()?, y: self.f32()?, radius: self.f32()? },
+            2 => {
+                let len = self.u64()? as usize;
+                if self.bytes.len() / 8 < len {
+                    return Err(SnapshotError::Truncated);
+                }
+                let points = (0..len).map(|_| Ok((self.f32()?, self.f32()?))).collect::<Result<_, SnapshotError>>()?;
+                SourcePath::Polyline(points)
+            },
+            _ => return Err(SnapshotError::InvalidValue("path")),
+        })
+    }
+
+    fn source(&mut self) -> Result<(f32, Waveform, Envelope, bool), SnapshotError> {
+        let amplitude = self.f32()?;
+        let waveform = self.waveform()?;
+        let envelope = Envelope {
+            start: self.f32()?,
+            stop: self.f32()?,
+            ramp: self.f32()?,
+        };
+        let enabled = self.u8()? != 0;
+        Ok((amplitude, waveform, envelope, enabled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundaries;
+    use super::super::Shape;
+
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 30;
+
+    // A simulation using every part of the state
+    fn scene() -> WaveEquation {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_stencil(Stencil::NinePoint);
+        let mut boundaries = Boundaries::uniform(BoundaryCondition::Absorbing);
+        boundaries.top = BoundaryCondition::Fixed;
+        wave_equation.set_boundary_conditions(boundaries);
+        wave_equation.set_sponge_layer(SpongeLayer { thickness: 4, strength: 2.0 });
+        wave_equation.set_damping(0.1);
+        wave_equation.medium_mut().fill(Shape::Circle { x: 25.0, y: 15.0, radius: 5.0 }, 0.5);
+        wave_equation.obstacles_mut().stamp_rectangle(10.0, 0.0, 11.0, 8.0, Obstacle::Free);
+
+        wave_equation.add_source(PointSource::new(20.5, 15.5, 1.0, 4.0));
+        let noise = Waveform::WhiteNoise { seed: 3, sample_rate: 10.0 };
+        wave_equation.add_plane_wave(PlaneWaveSource::new(Edge::Left, noise, 0.1));
+        let path = SourcePath::Polyline(vec![(5.5, 20.5), (30.5, 20.5), (30.5, 25.5)]);
+        let burst = Waveform::SquareBurst { frequency: 2.0, cycles: 4, delay: 0.5 };
+        wave_equation.add_moving_source(MovingSource::with_waveform(path, 0.5, burst, 2.0));
+
+        for i in 0..30 {
+            wave_equation.add_impulse(12.3, 5.7 + 0.4 * i as f32);
+            wave_equation.step(None);
+        }
+        wave_equation
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        for integrator in [Integrator::Verlet, Integrator::RungeKutta4] {
+            let mut wave_equation = scene();
+            wave_equation.set_integrator(integrator);
+            wave_equation.step(None);
+
+            let bytes = wave_equation.to_snapshot_bytes();
+            let mut loaded = WaveEquation::from_snapshot_bytes(&bytes).unwrap();
+            assert_eq!(loaded.to_snapshot_bytes(), bytes);
+            assert_eq!(loaded.steps(), 31);
+            assert_eq!(loaded.integrator(), integrator);
+            assert_eq!(loaded.sources().count() + loaded.plane_waves().count() + loaded.moving_sources().count(), 3);
+
+            // both continue bit for bit identically
+            for _i in 0..20 {
+                wave_equation.step(None);
+                loaded.step(None);
+            }
+            assert_eq!(loaded.get_current(), wave_equation.get_current());
+        }
+    }
+
+    #[test]
+    fn snapshot_file_round_trip() {
+        let wave_equation = scene();
+        let mut file = Vec::new();
+        wave_equation.save_snapshot(&mut file).unwrap();
+        let loaded = WaveEquation::load_snapshot(file.as_slice()).unwrap();
+        assert_eq!(loaded.get_current(), wave_equation.get_current());
+        assert_eq!(loaded.medium().wave_speed(), wave_equation.medium().wave_speed());
+        assert_eq!(loaded.obstacles().cells(), wave_equation.obstacles().cells());
+    }
+
+    #[test]
+    fn damaged_snapshots_are_rejected() {
+        let bytes = scene().to_snapshot_bytes();
+
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_SIZE + 100] ^= 1;
+        assert!(matches!(WaveEquation::from_snapshot_bytes(&corrupted), Err(SnapshotError::ChecksumMismatch)));
+
+        assert!(matches!(WaveEquation::from_snapshot_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+        assert!(matches!(WaveEquation::from_snapshot_bytes(&bytes[..10]), Err(SnapshotError::Truncated)));
+        assert!(matches!(WaveEquation::from_snapshot_bytes(b"PNG"), Err(SnapshotError::InvalidMagic)));
+
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(WaveEquation::from_snapshot_bytes(&newer), Err(SnapshotError::UnsupportedVersion(2))));
+    }
+}
@@ -0,0 +1,401 @@
+//! Persistent sources which excite the grid in every step
+//!
+
+use super::Edge;
+use super::Waveform;
+
+/// Switches a source on and off, with a smooth ramp to avoid a broadband click
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct Envelope {
+    /// time in s at which the source starts
+    pub start: f32,
+    /// time in s at which the source starts to fade out, infinite for sources which never stop
+    pub stop: f32,
+    /// duration in s of fading in and out
+    pub ramp: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            stop: f32::INFINITY,
+            ramp: 0.5,
+        }
+    }
+}
+
+impl Envelope {
+    /// Factor between 0 and 1 of the amplitude at time t
+    pub fn gain(&self, t: f32) -> f32 {
+        let rise = |s: f32| {
+            if self.ramp <= 0.0 {
+                return if s >= 0.0 { 1.0 } else { 0.0 };
+            }
+            // smoothstep, the derivative vanishes at both ends
+            let s = (s / self.ramp).clamp(0.0, 1.0);
+            s * s * (3.0 - 2.0 * s)
+        };
+        rise(t - self.start) * (1.0 - rise(t - self.stop))
+    }
+}
+
+/// Point force at a fixed position of the grid
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct PointSource {
+    /// position in nodes
+    pub x: f32,
+    pub y: f32,
+    /// force amplitude, add_impulse uses a force of 16
+    pub amplitude: f32,
+    pub waveform: Waveform,
+    pub envelope: Envelope,
+    /// disabled sources are kept, but do not inject any force
+    pub enabled: bool,
+}
+
+impl PointSource {
+    /// Creates a sine source with the frequency in Hz, which starts immediately and never stops
+    pub fn new(x: f32, y: f32, frequency: f32, amplitude: f32) -> Self {
+        Self::with_waveform(x, y, Waveform::sine(frequency), amplitude)
+    }
+
+    /// Creates a source which starts immediately and never stops
+    pub fn with_waveform(x: f32, y: f32, waveform: Waveform, amplitude: f32) -> Self {
+        Self {
+            x,
+            y,
+            amplitude,
+            waveform,
+            envelope: Envelope::default(),
+            enabled: true,
+        }
+    }
+
+    /// Force injected at time t
+    pub fn force(&self, t: f32) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+        self.amplitude * self.envelope.gain(t) * self.waveform.value(t)
+    }
+}
+
+/// Drives all nodes along one edge of the grid in phase, which sends a plane wave into the grid
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct PlaneWaveSource {
+    pub edge: Edge,
+    /// force amplitude per node
+    pub amplitude: f32,
+    pub waveform: Waveform,
+    pub envelope: Envelope,
+    pub enabled: bool,
+}
+
+impl PlaneWaveSource {
+    /// Creates a source which starts immediately and never stops
+    pub fn new(edge: Edge, waveform: Waveform, amplitude: f32) -> Self {
+        Self {
+            edge,
+            amplitude,
+            waveform,
+            envelope: Envelope::default(),
+            enabled: true,
+        }
+    }
+
+    /// Force injected into each node at time t
+    pub fn force(&self, t: f32) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+        self.amplitude * self.envelope.gain(t) * self.waveform.value(t)
+    }
+}
+
+/// Track of a moving source, coordinates are measured in nodes
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum SourcePath {
+    /// Straight line from the first to the second point
+    Line { x_0: f32, y_0: f32, x_1: f32, y_1: f32 },
+    /// Circle around a center, run counterclockwise starting to the right of the center
+    Circle { x: f32, y: f32, radius: f32 },
+    /// Straight lines through all points
+    Polyline(Vec<(f32, f32)>),
+}
+
+impl SourcePath {
+    /// Length of the path in nodes, of one lap for circles
+    pub fn length(&self) -> f32 {
+        match self {
+            SourcePath::Line { x_0, y_0, x_1, y_1 } => (x_1 - x_0).hypot(y_1 - y_0),
+            SourcePath::Circle { radius, .. } => 2.0 * std::f32::consts::PI * radius,
+            SourcePath::Polyline(points) => points.windows(2)
+                .map(|segment| (segment[1].0 - segment[0].0).hypot(segment[1].1 - segment[0].1))
+                .sum(),
+        }
+    }
+
+    /// Position after the distance in nodes along the path
+    /// Lines and polylines stay at their end, circles are run repeatedly
+    pub fn position(&self, distance: f32) -> (f32, f32) {
+        match self {
+            SourcePath::Line { x_0, y_0, x_1, y_1 } => {
+                let length = self.length();
+                let s = if length > 0.0 { (distance / length).clamp(0.0, 1.0) } else { 0.0 };
+                (x_0 + s * (x_1 - x_0), y_0 + s * (y_1 - y_0))
+            },
+            SourcePath::Circle { x, y, radius } => {
+                if *radius <= 0.0 {
+                    return (*x, *y);
+                }
+                let angle = distance / radius;
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            },
+            SourcePath::Polyline(points) => {
+                let mut distance = distance.max(0.0);
+                for segment in points.windows(2) {
+                    let ((x_0, y_0), (x_1, y_1)) = (segment[0], segment[1]);
+                    let length = (x_1 - x_0).hypot(y_1 - y_0);
+                    if distance <= length {
+                        let s = distance / length;
+                        return (x_0 + s * (x_1 - x_0), y_0 + s * (y_1 - y_0));
+                    }
+                    distance -= length;
+                }
+                // an empty polyline lies outside of the grid
+                points.last().copied().unwrap_or((-1.0, -1.0))
+            },
+        }
+    }
+}
+
+/// Point force which travels along a path, shows the Doppler effect and,
+/// faster than the waves, a Mach cone
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct MovingSource {
+    pub path: SourcePath,
+    /// speed along the path in length units per s like the wave speed, the source starts moving with its envelope
+    pub speed: f32,
+    /// force amplitude
+    pub amplitude: f32,
+    pub waveform: Waveform,
+    pub envelope: Envelope,
+    pub enabled: bool,
+}
+
+impl MovingSource {
+    /// Creates a sine source with the frequency in Hz, which starts immediately and never stops
+    pub fn new(path: SourcePath, speed: f32, frequency: f32, amplitude: f32) -> Self {
+        Self::with_waveform(path, speed, Waveform::sine(frequency), amplitude)
+    }
+
+    /// Creates a source which starts immediately and never stops
+    pub fn with_waveform(path: SourcePath, speed: f32, waveform: Waveform, amplitude: f32) -> Self {
+        Self {
+            path,
+            speed,
+            amplitude,
+            waveform,
+            envelope: Envelope::default(),
+            enabled: true,
+        }
+    }
+
+    /// Force injected at time t
+    pub fn force(&self, t: f32) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+        self.amplitude * self.envelope.gain(t) * self.waveform.value(t)
+    }
+
+    /// Position in nodes at time t on a grid with the node distance h
+    pub fn position(&self, t: f32, h: f32) -> (f32, f32) {
+        let distance = (t - self.envelope.start).max(0.0) * self.speed / h;
+        self.path.position(distance)
+    }
+}
+
+/// Handle of a source added to a wave equation, stays valid until the source is removed
+#[derive(Debug)]
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Copy, Clone)]
+pub struct SourceId(pub(super) u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundaries;
+    use super::super::BoundaryCondition;
+    use super::super::WaveEquation;
+
+    const WIDTH: usize = 61;
+    const HEIGHT: usize = 50;
+
+    #[test]
+    fn envelope_switches_smoothly() {
+        let envelope = Envelope { start: 1.0, stop: 3.0, ramp: 0.5 };
+        assert_eq!(envelope.gain(0.5), 0.0);
+        assert_eq!(envelope.gain(1.0), 0.0);
+        assert_eq!(envelope.gain(1.25), 0.5);
+        assert_eq!(envelope.gain(2.0), 1.0);
+        assert_eq!(envelope.gain(3.25), 0.5);
+        assert_eq!(envelope.gain(4.0), 0.0);
+
+        let source = PointSource { enabled: false, ..PointSource::new(1.0, 1.0, 1.0, 1.0) };
+        assert_eq!(source.force(0.25), 0.0);
+    }
+
+    #[test]
+    fn sources_can_be_edited_and_removed() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let a = wave_equation.add_source(PointSource::new(20.5, 25.0, 1.0, 4.0));
+        let b = wave_equation.add_source(PointSource::new(40.5, 25.0, 1.0, 4.0));
+        assert_ne!(a, b);
+        assert_eq!(wave_equation.sources().count(), 2);
+
+        wave_equation.source_mut(b).unwrap().waveform = Waveform::sine(2.0);
+        assert_eq!(wave_equation.source(b).unwrap().waveform, Waveform::sine(2.0));
+
+        assert_eq!(wave_equation.remove_source(a).unwrap().x, 20.5);
+        assert_eq!(wave_equation.remove_source(a), None);
+        assert_eq!(wave_equation.sources().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+
+        wave_equation.clear_sources();
+        assert_eq!(wave_equation.sources().count(), 0);
+    }
+
+    #[test]
+    fn source_oscillates_with_its_frequency() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Absorbing));
+        wave_equation.add_source(PointSource::new(30.0, 25.0, 1.0, 4.0));
+
+        // count the sign changes next to the source during 4 s, after the source is switched on
+        let mut crossings = 0;
+        let mut last = 0.0;
+        for step in 0..200 {
+            wave_equation.step(None);
+            let u = wave_equation.get_current()[25 * WIDTH + 35];
+            if step >= 120 && u * last < 0.0 {
+                crossings += 1;
+            }
+            last = u;
+        }
+        assert!((7..=9).contains(&crossings), "{crossings} zero crossings");
+    }
+
+    #[test]
+    fn opposite_sources_cancel_on_symmetry_line() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.add_source(PointSource::new(20.0, 25.0, 1.0, 4.0));
+        let waveform = Waveform::Sine { frequency: 1.0, phase: std::f32::consts::PI };
+        wave_equation.add_source(PointSource::with_waveform(40.0, 25.0, waveform, 4.0));
+
+        for _i in 0..100 {
+            wave_equation.step(None);
+        }
+        let max = wave_equation.max_displacement();
+        assert!(max > 0.01);
+        for y in 0..HEIGHT {
+            assert!(wave_equation.get_current()[y * WIDTH + 30].abs() < 1e-4 * max);
+        }
+    }
+
+    #[test]
+    fn plane_wave_travels_from_edge() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let mut boundaries = Boundaries::uniform(BoundaryCondition::Periodic);
+        boundaries.left = BoundaryCondition::Absorbing;
+        boundaries.right = BoundaryCondition::Absorbing;
+        wave_equation.set_boundary_conditions(boundaries);
+        let pulse = Waveform::Ricker { frequency: 0.5, delay: 2.0 };
+        let id = wave_equation.add_plane_wave(PlaneWaveSource::new(Edge::Left, pulse, 0.5));
+        assert_eq!(wave_equation.plane_wave(id).unwrap().edge, Edge::Left);
+
+        // the peak needs 20 * h / c = 2.5 s from x = 11 to x = 31
+        let mut arrival = [(0.0, 0.0); 2];
+        for _i in 0..200 {
+            wave_equation.step(None);
+            let u = wave_equation.get_current();
+            for y in 1..HEIGHT {
+                assert!((u[y * WIDTH + 31] - u[31]).abs() < 1e-5, "wave front is not planar");
+            }
+            for (arrival, x) in arrival.iter_mut().zip([11, 31]) {
+                if u[x] > arrival.1 {
+                    *arrival = (wave_equation.time(), u[x]);
+                }
+            }
+        }
+        let travel_time = arrival[1].0 - arrival[0].0;
+        assert!((travel_time - 2.5).abs() < 0.15, "pulse travelled {travel_time} s");
+
+        assert!(wave_equation.remove_plane_wave(id).is_some());
+        assert_eq!(wave_equation.plane_waves().count(), 0);
+    }
+
+    #[test]
+    fn paths_are_parametrized_by_length() {
+        let line = SourcePath::Line { x_0: 1.0, y_0: 1.0, x_1: 4.0, y_1: 5.0 };
+        assert_eq!(line.length(), 5.0);
+        assert_eq!(line.position(2.5), (2.5, 3.0));
+        assert_eq!(line.position(10.0), (4.0, 5.0));
+
+        let circle = SourcePath::Circle { x: 10.0, y: 10.0, radius: 2.0 };
+        let (x, y) = circle.position(std::f32::consts::PI);
+        assert!((x - 10.0).abs() < 1e-5 && (y - 12.0).abs() < 1e-5);
+
+        let polyline = SourcePath::Polyline(vec![(0.0, 0.0), (3.0, 0.0), (3.0, 2.0)]);
+        assert_eq!(polyline.length(), 5.0);
+        assert_eq!(polyline.position(4.0), (3.0, 1.0));
+        assert_eq!(polyline.position(6.0), (3.0, 2.0));
+    }
+
+    #[test]
+    fn moving_source_shows_doppler_shift() {
+        const WIDTH: usize = 200;
+        const HEIGHT: usize = 40;
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.set_damping(0.0);
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Absorbing));
+
+        // moves with half the wave speed from x = 60 to x = 140 during 20 s
+        let path = SourcePath::Line { x_0: 60.0, y_0: 20.0, x_1: 140.0, y_1: 20.0 };
+        let id = wave_equation.add_moving_source(MovingSource::new(path, 0.5, 0.5, 4.0));
+
+        // count the sign changes ahead at x = 180 and behind at x = 20, of waves emitted after 2 s while the source moves
+        let (mut ahead, mut behind) = (Vec::new(), Vec::new());
+        for _i in 0..440 {
+            wave_equation.step(None);
+            let t = wave_equation.time();
+            let u = wave_equation.get_current();
+            if t > 16.0 {
+                ahead.push(u[20 * WIDTH + 180]);
+            }
+            if (8.0..20.0).contains(&t) {
+                behind.push(u[20 * WIDTH + 20]);
+            }
+        }
+        let frequency = |u: &[f32]| {
+            let crossings = u.windows(2).filter(|u| u[0] * u[1] < 0.0).count();
+            crossings as f32 / 2.0 / (u.len() as f32 * wave_equation.delta_t)
+        };
+
+        // f / (1 - M) ahead and f / (1 + M) behind the source
+        let (ahead, behind) = (frequency(&ahead), frequency(&behind));
+        assert!((ahead - 1.0).abs() < 0.1, "{ahead} Hz ahead");
+        assert!((behind - 1.0 / 3.0).abs() < 0.05, "{behind} Hz behind");
+        assert_eq!(wave_equation.moving_source(id).unwrap().position(30.0, wave_equation.h), (140.0, 20.0));
+    }
+}
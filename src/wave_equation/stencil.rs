@@ -0,0 +1,166 @@
+//! Discretizations of the laplacian on the grid
+//!
+
+/// Finite difference stencil of the laplacian
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+#[derive(Default)]
+pub enum Stencil {
+    /// Second order cross of the 4 direct neighbours,
+    /// waves travel noticeably faster along the diagonals than along the axes
+    #[default]
+    FivePoint,
+    /// Second order stencil including the diagonal neighbours,
+    /// the leading error term is isotropic, so the wave speed hardly depends on the direction
+    NinePoint,
+    /// Fourth order cross reaching 2 nodes along each axis
+    /// The diagonal weights of the 13 point footprint vanish at this order, so only 9 nodes are used
+    FourthOrder,
+}
+
+const FIVE_POINT: &[(isize, isize, f32)] = &[
+    (-1, 0, 1.0),
+    (1, 0, 1.0),
+    (0, -1, 1.0),
+    (0, 1, 1.0),
+];
+
+const NINE_POINT: &[(isize, isize, f32)] = &[
+    (-1, 0, 4.0 / 6.0),
+    (1, 0, 4.0 / 6.0),
+    (0, -1, 4.0 / 6.0),
+    (0, 1, 4.0 / 6.0),
+    (-1, -1, 1.0 / 6.0),
+    (1, -1, 1.0 / 6.0),
+    (-1, 1, 1.0 / 6.0),
+    (1, 1, 1.0 / 6.0),
+];
+
+const FOURTH_ORDER: &[(isize, isize, f32)] = &[
+    (-2, 0, -1.0 / 12.0),
+    (-1, 0, 16.0 / 12.0),
+    (1, 0, 16.0 / 12.0),
+    (2, 0, -1.0 / 12.0),
+    (0, -2, -1.0 / 12.0),
+    (0, -1, 16.0 / 12.0),
+    (0, 1, 16.0 / 12.0),
+    (0, 2, -1.0 / 12.0),
+];
+
+impl Stencil {
+    /// Offsets x, y and weights of the neighbours, the laplacian is scaled by 1/h^2 afterwards
+    pub fn neighbours(&self) -> &'static [(isize, isize, f32)] {
+        match self {
+            Stencil::FivePoint => FIVE_POINT,
+            Stencil::NinePoint => NINE_POINT,
+            Stencil::FourthOrder => FOURTH_ORDER,
+        }
+    }
+
+    /// Weight of the center node, the negative sum of the neighbour weights
+    pub fn center(&self) -> f32 {
+        match self {
+            Stencil::FivePoint => -4.0,
+            Stencil::NinePoint => -20.0 / 6.0,
+            Stencil::FourthOrder => -5.0,
+        }
+    }
+
+    /// Number of nodes the stencil reaches in each direction
+    pub fn radius(&self) -> usize {
+        match self {
+            Stencil::FivePoint | Stencil::NinePoint => 1,
+            Stencil::FourthOrder => 2,
+        }
+    }
+
+    /// Largest stable courant number of the Verlet scheme, 2 / sqrt(largest eigenvalue of the stencil)
+    pub fn stability_limit(&self) -> f32 {
+        match self {
+            // eigenvalue 8
+            Stencil::FivePoint => std::f32::consts::FRAC_1_SQRT_2,
+            // eigenvalue 16/3
+            Stencil::NinePoint => 0.75_f32.sqrt(),
+            // eigenvalue 32/3
+            Stencil::FourthOrder => 0.375_f32.sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Boundaries;
+    use super::super::BoundaryCondition;
+    use super::super::WaveEquation;
+    use super::super::WaveEquationConfig;
+
+    const SIZE: usize = 64;
+
+    // Phase speed of a standing plane wave with k_x and k_y periods across the periodic grid
+    // Measured by the time it takes the standing wave to reach zero displacement, a quarter period
+    fn phase_speed(stencil: Stencil, k_x: usize, k_y: usize) -> f32 {
+        let mut config = WaveEquationConfig::new(SIZE, SIZE);
+        config.damping = 0.0;
+        config.stencil = stencil;
+        let mut wave_equation = WaveEquation::with_config(config).unwrap();
+        wave_equation.set_boundary_conditions(Boundaries::uniform(BoundaryCondition::Periodic));
+
+        let phase = |x: usize, y: usize| 2.0 * std::f32::consts::PI * (k_x * x + k_y * y) as f32 / SIZE as f32;
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                wave_equation.current[y * SIZE + x] = phase(x, y).cos();
+                wave_equation.previous[y * SIZE + x] = phase(x, y).cos();
+            }
+        }
+
+        let mut u_old = wave_equation.get_current()[0];
+        let mut steps = 0;
+        loop {
+            wave_equation.step(None);
+            steps += 1;
+            let u = wave_equation.get_current()[0];
+            if u <= 0.0 {
+                // interpolate the zero crossing between the last two steps,
+                // equal previous and current values put the turning point half a step before the start
+                let quarter_period = (steps as f32 + 0.5 - u / (u - u_old)) * wave_equation.delta_t;
+                let omega = 0.5 * std::f32::consts::PI / quarter_period;
+                let length = SIZE as f32 * wave_equation.h;
+                let k = 2.0 * std::f32::consts::PI * ((k_x * k_x + k_y * k_y) as f32).sqrt() / length;
+                return omega / k;
+            }
+            u_old = u;
+        }
+    }
+
+    // Relative difference of the phase speed along the diagonal and along the axis,
+    // with about 9 nodes per wavelength in both directions
+    fn anisotropy(stencil: Stencil) -> f32 {
+        let axis = phase_speed(stencil, 7, 0);
+        let diagonal = phase_speed(stencil, 5, 5);
+        assert!((axis - 1.0).abs() < 0.05, "{stencil:?} phase speed along the axis {axis}");
+        assert!((diagonal - 1.0).abs() < 0.05, "{stencil:?} phase speed along the diagonal {diagonal}");
+
+        (diagonal - axis).abs() / axis
+    }
+
+    #[test]
+    fn higher_order_stencils_are_more_isotropic() {
+        let five_point = anisotropy(Stencil::FivePoint);
+        let nine_point = anisotropy(Stencil::NinePoint);
+        let fourth_order = anisotropy(Stencil::FourthOrder);
+
+        assert!(five_point > 0.005, "five point anisotropy {five_point}");
+        assert!(nine_point < 0.5 * five_point, "nine point anisotropy {nine_point}, five point {five_point}");
+        assert!(fourth_order < 0.5 * five_point, "fourth order anisotropy {fourth_order}, five point {five_point}");
+    }
+
+    #[test]
+    fn stencils_vanish_for_constant_fields() {
+        for stencil in [Stencil::FivePoint, Stencil::NinePoint, Stencil::FourthOrder] {
+            let sum: f32 = stencil.neighbours().iter().map(|(_x, _y, weight)| weight).sum();
+            assert!((sum + stencil.center()).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Streams the colored displacement into animated gif files and uncompressed y4m videos
+//!
+//! Y4m files can be converted with ffmpeg, e.g. ffmpeg -i waves.y4m waves.mp4
+//!
+
+use std::io::Write;
+
+use image::imageops;
+
+use super::ColorRange;
+use super::Colormap;
+use super::FrameError;
+use super::WaveEquation;
+
+// Speed of the color quantization of gif frames from 1 to 30, higher is faster with worse colors
+const GIF_SPEED: i32 = 10;
+
+/// Part of the grid in nodes, y points up like in the grid
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct CropRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Which steps and which part of the grid are written as frames
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub struct FrameOptions {
+    /// Frames per second of the video
+    pub fps: u32,
+    /// Writes every nth step
+    pub stride: u64,
+    /// Writes only a part of the grid, the region is clamped to the grid but needs to start within it
+    pub crop: Option<CropRegion>,
+    /// Pixels per node, frames are resized with the nearest node
+    pub scale: f32,
+    pub colormap: Colormap,
+    pub range: ColorRange,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        Self {
+            fps: 25,
+            stride: 1,
+            crop: None,
+            scale: 1.0,
+            colormap: Colormap::Cubehelix,
+            range: ColorRange::default(),
+        }
+    }
+}
+
+impl FrameOptions {
+    /// Checks the crop region and the scale, which do not depend on the grid
+    pub fn validate(&self) -> Result<(), FrameError> {
+        if !(self.scale.is_finite() && self.scale > 0.0) {
+            return Err(FrameError::InvalidScale(self.scale));
+        }
+        match self.crop {
+            Some(crop) if crop.width == 0 || crop.height == 0 => Err(FrameError::InvalidCrop(crop)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the options for a grid of width x height nodes
+    pub fn validate_grid(&self, width: usize, height: usize) -> Result<(), FrameError> {
+        self.validate()?;
+        match self.crop {
+            Some(crop) if crop.x >= width || crop.y >= height => Err(FrameError::InvalidCrop(crop)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Colors the displacement, then crops and scales the image
+    pub fn render(&self, wave_equation: &WaveEquation) -> image::RgbaImage {
+        let mut image = wave_equation.to_image(self.colormap, self.range);
+
+        if let Some(crop) = self.crop {
+            // the rows of the image are flipped
+            let height = image.height();
+            let top = height.saturating_sub((crop.y + crop.height) as u32);
+            let bottom = height.saturating_sub(crop.y as u32);
+            image = imageops::crop_imm(&image, crop.x as u32, top, crop.width as u32, bottom - top).to_image();
+        }
+
+        if self.scale != 1.0 {
+            let width = ((image.width() as f32 * self.scale).round() as u32).max(1);
+            let height = ((image.height() as f32 * self.scale).round() as u32).max(1);
+            image = imageops::resize(&image, width, height, imageops::FilterType::Nearest);
+        }
+
+        image
+    }
+
+    fn is_frame(&self, wave_equation: &WaveEquation) -> bool {
+        wave_equation.steps().is_multiple_of(self.stride.max(1))
+    }
+}
+
+/// Writes an animated gif which repeats forever, the file is complete when the writer is dropped
+pub struct GifWriter<W: Write> {
+    encoder: image::codecs::gif::GifEncoder<W>,
+    options: FrameOptions,
+    frames: usize,
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(writer: W, options: FrameOptions) -> image::ImageResult<Self> {
+        options.validate()?;
+        let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(writer, GIF_SPEED);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            options,
+            frames: 0,
+        })
+    }
+
+    /// Writes a frame if the number of steps is a multiple of the stride, returns if it did
+    pub fn record(&mut self, wave_equation: &WaveEquation) -> image::ImageResult<bool> {
+        if !self.options.is_frame(wave_equation) {
+            return Ok(false);
+        }
+        self.write(wave_equation)?;
+        Ok(true)
+    }
+
+    /// Writes the current displacement as the next frame
+    pub fn write(&mut self, wave_equation: &WaveEquation) -> image::ImageResult<()> {
+        self.options.validate_grid(wave_equation.width(), wave_equation.height())?;
+        let delay = image::Delay::from_numer_denom_ms(1000, self.options.fps.max(1));
+        let frame = image::Frame::from_parts(self.options.render(wave_equation), 0, 0, delay);
+        self.encoder.encode_frame(frame)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
+
+/// Writes an uncompressed y4m video with 4:4:4 chroma, the size is taken from the first frame
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    options: FrameOptions,
+    size: Option<(u32, u32)>,
+    frames: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(writer: W, options: FrameOptions) -> Self {
+        Self {
+            writer,
+            options,
+            size: None,
+            frames: 0,
+        }
+    }
+
+    /// Writes a frame if the number of steps is a multiple of the stride, returns if it did
+    pub fn record(&mut self, wave_equation: &WaveEquation) -> std::io::Result<bool> {
+        if !self.options.is_frame(wave_equation) {
+            return Ok(false);
+        }
+        self.write(wave_equation)?;
+        Ok(true)
+    }
+
+    /// Writes the current displacement as the next frame, the first frame also writes the header
+    pub fn write(&mut self, wave_equation: &WaveEquation) -> std::io::Result<()> {
+        self.options.validate_grid(wave_equation.width(), wave_equation.height())?;
+        let image = self.options.render(wave_equation);
+        let size = image.dimensions();
+        match self.size {
+            None => {
+                writeln!(self.writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", size.0, size.1, self.options.fps.max(1))?;
+                self.size = Some(size);
+            },
+            Some(first) if first != size => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "frames of a y4m video need the same size"));
+            },
+            Some(_) => {},
+        }
+
+        // planes of luma and both chroma components
+        let pixels = image.pixels().count();
+        let mut planes = vec![0u8; 3 * pixels];
+        for (i, pixel) in image.pixels().enumerate() {
+            let [y, u, v] = rgb_to_yuv(pixel.0[0], pixel.0[1], pixel.0[2]);
+            planes[i] = y;
+            planes[pixels + i] = u;
+            planes[2 * pixels + i] = v;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Flushes the video and returns the writer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// BT.601 with the limited range, which players assume for y4m files
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+    let u = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+    let v = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+    [y, u, v].map(|c| c.round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+
+    const WIDTH: usize = 40;
+    const HEIGHT: usize = 30;
+
+    fn options() -> FrameOptions {
+        FrameOptions {
+            fps: 10,
+            stride: 3,
+            crop: Some(CropRegion { x: 5, y: 20, width: 20, height: 20 }),
+            scale: 2.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gif_frames_have_the_cropped_and_scaled_size() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        wave_equation.add_impulse(15.0, 20.0);
+        wave_equation.interupt_mouse();
+
+        let mut file = Vec::new();
+        let mut writer = GifWriter::new(&mut file, options()).unwrap();
+        for _i in 0..10 {
+            wave_equation.step(None);
+            writer.record(&wave_equation).unwrap();
+        }
+        assert_eq!(writer.frames(), 3);
+        drop(writer);
+
+        // the crop region ends at the top of the grid after 10 rows
+        let decoder = image::codecs::gif::GifDecoder::new(file.as_slice()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!(frame.buffer().dimensions(), (40, 20));
+            assert_eq!(frame.delay(), image::Delay::from_numer_denom_ms(100, 1));
+        }
+    }
+
+    #[test]
+    fn y4m_has_a_header_and_three_planes_per_frame() {
+        let mut wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let mut writer = Y4mWriter::new(Vec::new(), FrameOptions { stride: 2, ..options() });
+        for _i in 0..4 {
+            wave_equation.step(None);
+            writer.record(&wave_equation).unwrap();
+        }
+        assert_eq!(writer.frames(), 2);
+        let video = writer.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W40 H20 F10:1 Ip A1:1 C444\n";
+        assert!(video.starts_with(header));
+        assert_eq!(video.len(), header.len() + 2 * (b"FRAME\n".len() + 3 * 40 * 20));
+        assert_eq!(&video[header.len()..header.len() + 6], b"FRAME\n");
+
+        assert_eq!(rgb_to_yuv(0, 0, 0), [16, 128, 128]);
+        assert_eq!(rgb_to_yuv(255, 255, 255), [235, 128, 128]);
+    }
+
+    #[test]
+    fn invalid_crop_and_scale_are_rejected() {
+        let wave_equation = WaveEquation::new(WIDTH, HEIGHT);
+        let crop = |x, y, width, height| FrameOptions { crop: Some(CropRegion { x, y, width, height }), ..options() };
+
+        assert!(GifWriter::new(Vec::new(), crop(5, 5, 0, 10)).is_err());
+        assert!(GifWriter::new(Vec::new(), FrameOptions { scale: 0.0, ..options() }).is_err());
+        assert!(GifWriter::new(Vec::new(), FrameOptions { scale: f32::NAN, ..options() }).is_err());
+        assert_eq!(crop(5, 5, 10, 0).validate(), Err(FrameError::InvalidCrop(CropRegion { x: 5, y: 5, width: 10, height: 0 })));
+        assert_eq!(FrameOptions { scale: f32::INFINITY, ..options() }.validate(), Err(FrameError::InvalidScale(f32::INFINITY)));
+
+        // the crop region needs to start within the grid
+        let mut gif = GifWriter::new(Vec::new(), crop(WIDTH, 0, 10, 10)).unwrap();
+        assert!(gif.write(&wave_equation).is_err());
+        let mut y4m = Y4mWriter::new(Vec::new(), crop(0, HEIGHT, 10, 10));
+        assert_eq!(y4m.write(&wave_equation).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(y4m.frames(), 0);
+        assert!(crop(WIDTH - 1, HEIGHT - 1, 10, 10).validate_grid(WIDTH, HEIGHT).is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+//! Source time functions to excite the grid
+//!
+//! A waveform maps a time in s to a value of about -1 to 1. Used as a function of distance / wave speed,
+//! it also describes the spatial profile of a wave packet.
+//!
+
+/// Standard source time function
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Copy, Clone)]
+pub enum Waveform {
+    /// sin(2 pi f t + phase)
+    Sine { frequency: f32, phase: f32 },
+    /// Gaussian bell with the standard deviation width in s, peaks with 1 at delay
+    GaussianPulse { delay: f32, width: f32 },
+    /// Second derivative of a gaussian (mexican hat), peaks with 1 at delay,
+    /// the amplitude spectrum peaks at frequency
+    Ricker { frequency: f32, delay: f32 },
+    /// Linear frequency sweep from start_frequency to end_frequency during duration s, then silent
+    Chirp { start_frequency: f32, end_frequency: f32, duration: f32 },
+    /// Square wave of a number of cycles starting at delay, then silent
+    SquareBurst { frequency: f32, cycles: u32, delay: f32 },
+    /// Uniform noise between -1 and 1, which holds each value for 1 / sample_rate s,
+    /// the same seed always gives the same sequence
+    WhiteNoise { seed: u64, sample_rate: f32 },
+}
+
+impl Waveform {
+    /// Sine wave starting with zero displacement
+    pub fn sine(frequency: f32) -> Self {
+        Waveform::Sine { frequency, phase: 0.0 }
+    }
+
+    pub fn value(&self, t: f32) -> f32 {
+        use std::f32::consts::PI;
+
+        match *self {
+            Waveform::Sine { frequency, phase } => (2.0 * PI * frequency * t + phase).sin(),
+            Waveform::GaussianPulse { delay, width } => {
+                let s = (t - delay) / width;
+                (-0.5 * s * s).exp()
+            },
+            Waveform::Ricker { frequency, delay } => {
+                let s = (PI * frequency * (t - delay)).powi(2);
+                (1.0 - 2.0 * s) * (-s).exp()
+            },
+            Waveform::Chirp { start_frequency, end_frequency, duration } => {
+                if t < 0.0 || t > duration {
+                    return 0.0;
+                }
+                let sweep = (end_frequency - start_frequency) / duration;
+                (2.0 * PI * (start_frequency * t + 0.5 * sweep * t * t)).sin()
+            },
+            Waveform::SquareBurst { frequency, cycles, delay } => {
+                let cycle = (t - delay) * frequency;
+                if cycle < 0.0 || cycle >= cycles as f32 {
+                    0.0
+                }
+                else if cycle.fract() < 0.5 {
+                    1.0
+                }
+                else {
+                    -1.0
+                }
+            },
+            Waveform::WhiteNoise { seed, sample_rate } => {
+                let sample = (t * sample_rate).floor() as i64 as u64;
+                let bits = splitmix64(seed ^ splitmix64(sample));
+                // the upper 24 bits give a uniform number in [0, 1)
+                (bits >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+            },
+        }
+    }
+}
+
+// Mixes the bits of x, a simple hash with good statistical quality
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_T: f32 = 0.005;
+    const SAMPLES: usize = 2000;
+
+    // Amplitude spectrum of the waveform sampled from 0 to 10 s at the given frequencies
+    fn spectrum(waveform: Waveform, frequencies: &[f32]) -> Vec<f32> {
+        frequencies.iter().map(|frequency| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for i in 0..SAMPLES {
+                let t = i as f32 * DELTA_T;
+                let angle = 2.0 * std::f32::consts::PI * frequency * t;
+                re += waveform.value(t) * angle.cos() * DELTA_T;
+                im -= waveform.value(t) * angle.sin() * DELTA_T;
+            }
+            (re * re + im * im).sqrt()
+        }).collect()
+    }
+
+    // Frequency with the largest amplitude between 0.1 and 10 Hz
+    fn peak_frequency(waveform: Waveform) -> f32 {
+        let frequencies: Vec<f32> = (1..=200).map(|i| i as f32 * 0.05).collect();
+        let spectrum = spectrum(waveform, &frequencies);
+        let peak = (0..spectrum.len()).max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b])).unwrap();
+        frequencies[peak]
+    }
+
+    // Time with the largest value between 0 and 10 s
+    fn peak_time(waveform: Waveform) -> f32 {
+        let peak = (0..SAMPLES).max_by(|a, b| {
+            waveform.value(*a as f32 * DELTA_T).total_cmp(&waveform.value(*b as f32 * DELTA_T))
+        }).unwrap();
+        peak as f32 * DELTA_T
+    }
+
+    #[test]
+    fn sine_has_single_frequency() {
+        assert!((peak_frequency(Waveform::sine(2.0)) - 2.0).abs() < 0.06);
+        assert_eq!(Waveform::sine(2.0).value(0.0), 0.0);
+    }
+
+    #[test]
+    fn gaussian_pulse_peaks_at_delay() {
+        let pulse = Waveform::GaussianPulse { delay: 3.0, width: 0.2 };
+        assert!((peak_time(pulse) - 3.0).abs() < DELTA_T);
+        assert_eq!(pulse.value(3.0), 1.0);
+        assert!((pulse.value(3.2) - (-0.5_f32).exp()).abs() < 1e-6);
+
+        // the spectrum of a gaussian is a gaussian around 0 Hz with the width 1 / (2 pi * 0.2)
+        let spectrum = spectrum(pulse, &[0.0, 0.8]);
+        let expected = (-0.5 * (2.0 * std::f32::consts::PI * 0.2 * 0.8_f32).powi(2)).exp();
+        assert!((spectrum[1] / spectrum[0] - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn ricker_wavelet_peaks_at_frequency() {
+        let ricker = Waveform::Ricker { frequency: 2.0, delay: 1.0 };
+        assert!((peak_time(ricker) - 1.0).abs() < DELTA_T);
+        assert_eq!(ricker.value(1.0), 1.0);
+        assert!((peak_frequency(ricker) - 2.0).abs() < 0.06);
+    }
+
+    #[test]
+    fn chirp_sweeps_frequencies() {
+        let chirp = Waveform::Chirp { start_frequency: 1.0, end_frequency: 4.0, duration: 6.0 };
+
+        // the instantaneous frequency follows from the number of zero crossings in a window
+        let frequency = |t_0: f32, t_1: f32| {
+            let samples = ((t_1 - t_0) / DELTA_T) as usize;
+            let crossings = (0..samples).filter(|i| {
+                let t = t_0 + *i as f32 * DELTA_T;
+                chirp.value(t) * chirp.value(t + DELTA_T) < 0.0
+            }).count();
+            crossings as f32 / 2.0 / (t_1 - t_0)
+        };
+        assert!((frequency(0.0, 1.0) - 1.25).abs() < 0.3);
+        assert!((frequency(5.0, 6.0) - 3.75).abs() < 0.3);
+        assert_eq!(chirp.value(7.0), 0.0);
+    }
+
+    #[test]
+    fn square_burst_has_cycles() {
+        let burst = Waveform::SquareBurst { frequency: 2.0, cycles: 3, delay: 1.0 };
+        assert_eq!(burst.value(0.9), 0.0);
+        assert_eq!(burst.value(1.1), 1.0);
+        assert_eq!(burst.value(1.4), -1.0);
+        assert_eq!(burst.value(2.6), 0.0);
+        assert!((peak_frequency(burst) - 2.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn white_noise_is_reproducible_and_flat() {
+        let noise = Waveform::WhiteNoise { seed: 7, sample_rate: 200.0 };
+        let other = Waveform::WhiteNoise { seed: 8, sample_rate: 200.0 };
+        let values: Vec<f32> = (0..SAMPLES).map(|i| noise.value(i as f32 * DELTA_T)).collect();
+        assert_eq!(values, (0..SAMPLES).map(|i| noise.value(i as f32 * DELTA_T)).collect::<Vec<_>>());
+        assert_ne!(values, (0..SAMPLES).map(|i| other.value(i as f32 * DELTA_T)).collect::<Vec<_>>());
+
+        // uniform distribution between -1 and 1
+        let mean = values.iter().sum::<f32>() / SAMPLES as f32;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / SAMPLES as f32;
+        assert!(values.iter().all(|v| (-1.0..1.0).contains(v)));
+        assert!(mean.abs() < 0.05);
+        assert!((variance - 1.0 / 3.0).abs() < 0.03);
+
+        // the power is spread evenly, compare a low and a high band
+        let power = |frequencies: Vec<f32>| spectrum(noise, &frequencies).iter().map(|a| a * a).sum::<f32>();
+        let low = power((1..=100).map(|i| i as f32 * 0.1).collect());
+        let high = power((1..=100).map(|i| 20.0 + i as f32 * 0.1).collect());
+        assert!((low / high - 1.0).abs() < 0.5, "low {low} high {high}");
+    }
+}
@@ -1,6 +1,7 @@
 //! Gui for the wave simulation app
 
 use crate::renderer;
+use crate::wave_equation;
 
 use super::gui;
 use super::vertex_texture_shader;
@@ -14,6 +15,7 @@ pub enum ButtonOptionsId{
     SwitchViewPoint,
     SwitchTexture,
     PerformanceGraph,
+    Preset(wave_equation::Preset),
 }
 
 #[derive(Debug)]
@@ -25,7 +27,8 @@ enum ButtonMenuId{
 
 #[derive(Copy, Clone)]
 enum LabelId{
-    Fps
+    Fps,
+    Diagnostics,
 }
 
 struct BtnMesh {
@@ -90,7 +93,14 @@ pub struct WaveSimGui {
     lbl_fps_host: label::Label,
     lbl_fps_mesh: label::LabelMesh, 
 
+    lbl_diagnostics_host: label::Label,
+    lbl_diagnostics_mesh: label::LabelMesh,
+
+    // Presets, one text button per preset in the order of Preset::ALL
+    lbl_preset_meshes: Vec<label::LabelMesh>,
+
     show_submenu: bool,
+    show_diagnostics: bool,
 }
 
 impl WaveSimGui {
@@ -147,6 +157,32 @@ impl WaveSimGui {
         let lbl_fps_host = label::Label::new(
             &font, 20.0, "60 fps  "
         );
+        let lbl_diagnostics_host = label::Label::new(
+            &font, 20.0, &Self::diagnostics_text(&wave_equation::Diagnostics {
+                step: 0,
+                kinetic_energy: -1.0e10,
+                potential_energy: -1.0e10,
+                total_energy: -1.0e10,
+                momentum: 0.0,
+                mass: 0.0,
+                max_amplitude: 1.0e10,
+                rms: 1.0e10,
+            })
+        );
+
+        // Presets
+        let lbl_preset_hosts: Vec<label::Label> = wave_equation::Preset::ALL.iter()
+            .map(|preset| label::Label::new(&font, 20.0, preset.name()))
+            .collect();
+        let preset_layout = gui::VerticalLayout::<ButtonOptionsId, LabelId>::new(
+            wave_equation::Preset::ALL.iter().zip(&lbl_preset_hosts)
+                .map(|(preset, host)| gui::GuiElement::Button(gui::Button::new(
+                    host.width(),
+                    host.height(),
+                    btn_boarder,
+                    ButtonOptionsId::Preset(*preset))))
+                .collect()
+        );
 
         // Options
         let vertical_layout =  gui::VerticalLayout::<ButtonOptionsId, LabelId>::new(vec![
@@ -184,12 +220,30 @@ impl WaveSimGui {
                         lbl_fps_host.height(), 
                         btn_boarder,
                         LabelId::Fps))),                    
+                gui::AlignedElement::new(
+                    gui::Alignment::TopLeft, 
+                    5 + lbl_fps_host.width() + 2*btn_boarder, 
+                    5, 
+                    gui::GuiElement::Label(gui::Label::new(
+                        lbl_diagnostics_host.width(), 
+                        lbl_diagnostics_host.height(), 
+                        btn_boarder,
+                        LabelId::Diagnostics))),
+                gui::AlignedElement::new(
+                    gui::Alignment::TopLeft, 
+                    5, 
+                    5 + lbl_fps_host.height() + 2*btn_boarder, 
+                    gui::GuiElement::VerticalLayout(preset_layout)),
         ]
         );
         let mut lbl_fps_instance = vertex_texture_shader::Instance::zero();
+        let mut lbl_diagnostics_instance = vertex_texture_shader::Instance::zero();
         let mut btn_switch_view_point_instance = vertex_texture_shader::Instance::zero();
         let mut btn_switch_texture_instance = vertex_texture_shader::Instance::zero();
         let mut btn_performance_graph_instance = vertex_texture_shader::Instance::zero();
+        let mut lbl_preset_instances: Vec<vertex_texture_shader::Instance> = wave_equation::Preset::ALL.iter()
+            .map(|_| vertex_texture_shader::Instance::zero())
+            .collect();
         let events = gui_options.resize(width, height);
         for event in &events {
             match event.element_id {
@@ -210,6 +264,12 @@ impl WaveSimGui {
                             btn_performance_graph_instance.position.y = event.y as f32;
                             btn_performance_graph_instance.position.z = z;
                         },
+                        ButtonOptionsId::Preset(preset) => {
+                            let instance = &mut lbl_preset_instances[Self::preset_index(preset)];
+                            instance.position.x = event.x as f32;
+                            instance.position.y = event.y as f32;
+                            instance.position.z = z;
+                        },
                     }
                 },
                 gui::ElementId::Label(label_id) =>  {
@@ -219,6 +279,11 @@ impl WaveSimGui {
                             lbl_fps_instance.position.y = event.y as f32;
                             lbl_fps_instance.position.z = z;
                         },
+                        LabelId::Diagnostics => {
+                            lbl_diagnostics_instance.position.x = event.x as f32;
+                            lbl_diagnostics_instance.position.y = event.y as f32;
+                            lbl_diagnostics_instance.position.z = z;
+                        },
                     }
                  }
             }
@@ -242,6 +307,18 @@ impl WaveSimGui {
             texture_bind_group_layout,
             &btn_performance_graph_instance);
 
+        let lbl_diagnostics_mesh = label::LabelMesh::new(wgpu_renderer, 
+            lbl_diagnostics_host.get_image(), 
+            texture_bind_group_layout,
+            &lbl_diagnostics_instance);
+
+        let lbl_preset_meshes = lbl_preset_hosts.iter().zip(&lbl_preset_instances)
+            .map(|(host, instance)| label::LabelMesh::new(wgpu_renderer, 
+                host.get_image(), 
+                texture_bind_group_layout,
+                instance))
+            .collect();
+
         // lbl_fps_host.get_image().save("fps_image.png").unwrap();
 
 
@@ -263,12 +340,22 @@ impl WaveSimGui {
             lbl_fps_host,
             lbl_fps_mesh,
 
+            lbl_diagnostics_host,
+            lbl_diagnostics_mesh,
+
+            lbl_preset_meshes,
+
             show_submenu: false,
+            show_diagnostics: false,
 
         }
     }
 
 
+    fn preset_index(preset: wave_equation::Preset) -> usize {
+        wave_equation::Preset::ALL.iter().position(|p| *p == preset).unwrap()
+    }
+
     fn vertices(width: u32, height: u32) -> [vertex_texture_shader::Vertex; 4]
     {
         let width = width as f32;
@@ -362,6 +449,31 @@ impl WaveSimGui {
         self.lbl_fps_mesh.update_texture(queue, self.lbl_fps_host.get_image());
     }
 
+    fn diagnostics_text(diagnostics: &wave_equation::Diagnostics) -> String {
+        format!("E {:9.2e} = {:9.2e} + {:9.2e}  max {:8.2e}  rms {:8.2e}", 
+            diagnostics.total_energy, 
+            diagnostics.kinetic_energy, 
+            diagnostics.potential_energy, 
+            diagnostics.max_amplitude, 
+            diagnostics.rms)
+    }
+
+    pub fn set_diagnostics<'a>(&mut self, queue: &wgpu::Queue, font: &'a rusttype::Font, diagnostics: &wave_equation::Diagnostics) {
+
+        let text = Self::diagnostics_text(diagnostics);
+        self.lbl_diagnostics_host.update(font, &text);
+        self.lbl_diagnostics_mesh.update_texture(queue, self.lbl_diagnostics_host.get_image());
+    }
+
+    pub fn show_diagnostics(&self) -> bool {
+        self.show_diagnostics
+    }
+
+    /// Shows the energy readout next to the fps label
+    pub fn set_show_diagnostics(&mut self, show: bool) {
+        self.show_diagnostics = show;
+    }
+
     pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
     {
         self.btn_vertex_buffer.bind(render_pass);
@@ -384,6 +496,14 @@ impl WaveSimGui {
             render_pass.draw_indexed(0..self.btn_index_buffer.size(), 0, 0..1);
 
             self.lbl_fps_mesh.draw(render_pass);
+
+            for lbl_preset_mesh in &self.lbl_preset_meshes {
+                lbl_preset_mesh.draw(render_pass);
+            }
+        }
+
+        if self.show_diagnostics {
+            self.lbl_diagnostics_mesh.draw(render_pass);
         }
     }
 
@@ -409,9 +529,13 @@ impl WaveSimGui {
         }
 
         let mut lbl_fps_instance = vertex_texture_shader::Instance::zero();
+        let mut lbl_diagnostics_instance = vertex_texture_shader::Instance::zero();
         let mut btn_switch_view_point_instance = vertex_texture_shader::Instance::zero();
         let mut btn_switch_texture_instance = vertex_texture_shader::Instance::zero();
         let mut btn_performance_graph_instance = vertex_texture_shader::Instance::zero();
+        let mut lbl_preset_instances: Vec<vertex_texture_shader::Instance> = wave_equation::Preset::ALL.iter()
+            .map(|_| vertex_texture_shader::Instance::zero())
+            .collect();
         let events = self.gui_options.resize(width, height);
         for event in &events {
             match event.element_id {
@@ -429,6 +553,11 @@ impl WaveSimGui {
                             btn_performance_graph_instance.position.x = event.x as f32;
                             btn_performance_graph_instance.position.y = event.y as f32;
                         },
+                        ButtonOptionsId::Preset(preset) => {
+                            let instance = &mut lbl_preset_instances[Self::preset_index(preset)];
+                            instance.position.x = event.x as f32;
+                            instance.position.y = event.y as f32;
+                        },
                     }
                 },
                 gui::ElementId::Label(label_id) =>  { 
@@ -437,6 +566,10 @@ impl WaveSimGui {
                             lbl_fps_instance.position.x = event.x as f32;
                             lbl_fps_instance.position.y = event.y as f32;
                         },
+                        LabelId::Diagnostics => {
+                            lbl_diagnostics_instance.position.x = event.x as f32;
+                            lbl_diagnostics_instance.position.y = event.y as f32;
+                        },
                     }
                 }
             }
@@ -444,8 +577,12 @@ impl WaveSimGui {
 
         self.btn_menu_mesh.update_instance_buffer(queue, &btn_menu_instance);
         self.lbl_fps_mesh.update_instance_buffer(queue, &lbl_fps_instance);
+        self.lbl_diagnostics_mesh.update_instance_buffer(queue, &lbl_diagnostics_instance);
         self.btn_switch_view_point_mesh.update_instance_buffer(queue, &btn_switch_view_point_instance);
         self.btn_switch_texture_mesh.update_instance_buffer(queue, &btn_switch_texture_instance);
         self.btn_performance_graph_mesh.update_instance_buffer(queue, &btn_performance_graph_instance);
+        for (lbl_preset_mesh, instance) in self.lbl_preset_meshes.iter_mut().zip(&lbl_preset_instances) {
+            lbl_preset_mesh.update_instance_buffer(queue, instance);
+        }
     }
 }
\ No newline at end of file